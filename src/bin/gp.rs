@@ -0,0 +1,43 @@
+//! Run a stage of the pipeline over a data root, which may be configured rather than given.
+//!
+//! ```text
+//! gp <filter|fetch|meta|verify|transform|build|export|serve> [data_root] [options] [--json]
+//! ```
+//! The exit status is an error if the command fails, or if part of its work failed, such as a page
+//! of an export.
+
+use graphics_pipeline::cli::Cli;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let cli = match Cli::from_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE
+        },
+    };
+
+    let report = match (cli.run(), cli.json) {
+        (Ok(report), true) => {
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            report
+        },
+        (Ok(report), false) => {
+            print!("{}", report);
+            report
+        },
+        (Err(e), true) => {
+            println!("{}", serde_json::json!({ "error": e.to_string() }));
+            return ExitCode::FAILURE
+        },
+        (Err(e), false) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE
+        },
+    };
+    match report.is_ok() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
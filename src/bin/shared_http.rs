@@ -0,0 +1,22 @@
+//! Serve time-series pages from a data root.
+//!
+//! ```text
+//! shared_http [data_root] [--config <file>] [--bind <address>] [--port <port>]
+//!     [--series-spec <file>] [--watch]
+//! ```
+//!
+//! Arguments override the environment (`GP_DATA_ROOT`, `GP_BIND`, `GP_PORT`), which overrides the
+//! config file given by `--config` or `GP_CONFIG`.
+
+use graphics_pipeline::{
+    config::PipelineConfig,
+    server::{run, ServerArgs},
+};
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    let args = ServerArgs::from_args(std::env::args().skip(1))?;
+    let config = PipelineConfig::load(args.config.as_deref(), args.layer)?
+        .server_config(&args.series_spec, args.watch);
+    Ok(run(config).await?)
+}
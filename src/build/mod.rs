@@ -0,0 +1,837 @@
+//! Rebuild only the parts of the pipeline whose inputs have changed.
+//!
+//! The stages of the pipeline form a graph of targets keyed on `SeriesId`
+//! ```text
+//! filter spec ──> series spec ──> raw data ──> meta ──> transformed data ──> pages
+//!                                                  └──> ts spec ──> ranges ──┘
+//! ```
+//! and after a target is built the hash of its inputs is recorded in `build.keytree` in the data
+//! root, such as
+//! ```text
+//! build:
+//!     target:
+//!         name:       raw u australia AUSURAMS
+//!         inputs:     9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! ```
+//! A target is planned when its output is missing, it has no record, its inputs have changed, or
+//! a target it depends on is planned. Raw data and meta are downloaded from FRED. The ts spec and
+//! its ranges are only targets when a file is given to regenerate, and pages only when there is an
+//! output directory. Transformed data, which this crate cannot make yet, is used as it is on disk:
+//! it is recorded by the hash of its file and reported as stale when the file changes, or skipped
+//! along with the targets depending on it if it is missing. Pages read the transformed data as an
+//! input, so they are planned when it changes rather than after it.
+
+use anyhow::anyhow;
+use crate::{
+    countries::Country,
+    error::{Error, Result},
+    export::{export_assets, export_indexes, export_page, export_series},
+    file_resources::{from_path_arg, IntoResources},
+    file_resources::impls::{Spec, TSHtmlTemplate, TSPageSpec},
+    filter_to_series::{select_series, series_spec_from_selections, Selection},
+    fred_client::{FredClient, FredConfig},
+    manifest::checksum,
+    primitives::{DataType, SeriesId},
+    series_spec::{series_spec_from_file, SeriesSpec, SeriessSpec},
+    series_to_disk::fetch_series,
+    series_to_meta::write_meta_file,
+    ts_graphics::html::{page_path, RenderOptions, SeriesDataMode},
+    ts_graphics::series_data::SeriesKey,
+    ts_graphics::template::Templates,
+    ts_graphics::ts_spec::{ts_spec_from_file, ts_spec_from_resources, write_ranges, PageSpec},
+    ts_graphics::ts_spec::generate::generate_ts_spec_file,
+};
+use key_tree::{KeyTree, KeyTreeError};
+use key_tree::serialize::{IntoKeyTree, KeyTreeString};
+use serde::{Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file in the data root which records the inputs of every target built.
+pub const BUILD_FILE: &str = "build.keytree";
+
+// === Target =====================================================================================
+
+/// A file written by a stage of the pipeline. Targets are ordered by stage, so every target comes
+/// after the targets it depends on.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Target {
+    SeriesSpec,
+    Raw(DataType, Country, SeriesId),
+    Meta(DataType, Country, SeriesId),
+    Transformed(DataType, Country, SeriesId),
+    TSSpec,
+    Ranges,
+    Page(DataType, Country, usize),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::SeriesSpec => write!(f, "series_spec"),
+            Target::Raw(data_type, country, series_id) => {
+                write!(f, "raw {} {} {}", data_type, country.as_filepath(), series_id)
+            },
+            Target::Meta(data_type, country, series_id) => {
+                write!(f, "meta {} {} {}", data_type, country.as_filepath(), series_id)
+            },
+            Target::Transformed(data_type, country, series_id) => {
+                write!(f, "transformed {} {} {}", data_type, country.as_filepath(), series_id)
+            },
+            Target::TSSpec => write!(f, "ts_spec"),
+            Target::Ranges => write!(f, "ranges"),
+            Target::Page(data_type, country, index) => {
+                write!(f, "page {} {} {}", data_type, country.as_filepath(), index)
+            },
+        }
+    }
+}
+
+impl Serialize for Target {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// An input of a target. Files are hashed by their contents, and values such as the spec of a
+// page by themselves.
+#[derive(Clone, Debug)]
+enum Input {
+    File(PathBuf),
+    Value(String),
+}
+
+// A target with the file it writes, what it is made from and the targets it depends on.
+#[derive(Debug)]
+struct Node {
+    output: PathBuf,
+    inputs: Vec<Input>,
+    deps:   Vec<Target>,
+}
+
+impl Node {
+
+    // The hash of the inputs. A missing file hashes differently from an empty one, and paths are
+    // left out so that the data root can be moved.
+    fn hash(&self) -> Result<String> {
+        let mut s = String::new();
+        for input in self.inputs.iter() {
+            match input {
+                Input::File(path) => match path.is_file() {
+                    true => s.push_str(&checksum(&fs::read(path)?)),
+                    false => s.push_str("missing"),
+                },
+                Input::Value(value) => s.push_str(value),
+            }
+            s.push('\n');
+        }
+        Ok(checksum(s.as_bytes()))
+    }
+}
+
+// === BuildGraph =================================================================================
+
+/// What to build and where.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildOptions {
+
+    /// The series specification in the `specs` directory.
+    pub series_spec:    String,
+
+    /// A filter spec in the `specs` directory to regenerate the series specification from.
+    pub filter_spec:    Option<String>,
+
+    /// A file in `ts_graphics/spec` to regenerate from the series specification, with the ranges
+    /// of its graphics computed from the data. Without it the specs of pages are used as they are.
+    pub ts_spec:        Option<String>,
+
+    /// The directory pages are rendered into. Without it no pages are built.
+    pub out_dir:        Option<PathBuf>,
+
+    /// Plan the build without running it.
+    pub dry_run:        bool,
+
+    /// The number of buckets of series worked on at once.
+    pub workers:        usize,
+
+    /// The FRED client settings used to regenerate the series specification.
+    pub fred:           FredConfig,
+}
+
+/// Every target of a data root, with its inputs and dependencies.
+#[derive(Debug)]
+pub struct BuildGraph {
+    nodes: BTreeMap<Target, Node>,
+    pages: Vec<PageSpec>,
+}
+
+impl BuildGraph {
+    pub fn new(data_root: &Path, options: &BuildOptions) -> Result<Self> {
+        let mut nodes = BTreeMap::new();
+        let spec_dir = Spec.dir(data_root)?;
+        let series_spec_path = spec_dir.join(&options.series_spec);
+
+        let mut spec_deps = Vec::new();
+        if let Some(filter_spec) = &options.filter_spec {
+            nodes.insert(Target::SeriesSpec, Node {
+                output: series_spec_path.clone(),
+                inputs: vec!(Input::File(spec_dir.join(filter_spec))),
+                deps:   Vec::new(),
+            });
+            spec_deps.push(Target::SeriesSpec);
+        }
+
+        // The series specification may not have been generated yet.
+        let seriess = match series_spec_path.is_file() {
+            true => series_spec_from_file(data_root, Path::new(&options.series_spec))?,
+            false => SeriessSpec { series: Vec::new() },
+        };
+
+        for series_spec in seriess.iter() {
+            let (data_type, country, series_id) =
+                (series_spec.data_type(), series_spec.country(), series_spec.series_id());
+            let raw = data_path(data_root, "raw_data", data_type, country, &series_id, "csv");
+            let spec_value = Input::Value(series_spec.keytree().to_string());
+
+            nodes.insert(Target::Raw(data_type, country, series_id.clone()), Node {
+                output: raw.clone(),
+                inputs: vec!(spec_value.clone()),
+                deps:   spec_deps.clone(),
+            });
+            nodes.insert(Target::Meta(data_type, country, series_id.clone()), Node {
+                output: data_path(data_root, "raw_data", data_type, country, &series_id, "meta"),
+                inputs: vec!(spec_value, Input::File(raw)),
+                deps:   vec!(Target::Raw(data_type, country, series_id)),
+            });
+        }
+
+        let mut page_deps = Vec::new();
+        if let Some(ts_spec_file) = &options.ts_spec {
+            let ts_spec_path = TSPageSpec.dir(data_root)?.join(ts_spec_file);
+
+            // Source graphics are captioned with the titles in the `.meta` files.
+            let mut inputs = vec!(Input::File(series_spec_path.clone()));
+            let mut deps = spec_deps.clone();
+            for series_spec in seriess.iter() {
+                let (data_type, country, series_id) =
+                    (series_spec.data_type(), series_spec.country(), series_spec.series_id());
+                inputs.push(Input::File(
+                    data_path(data_root, "raw_data", data_type, country, &series_id, "meta"),
+                ));
+                deps.push(Target::Meta(data_type, country, series_id));
+            }
+            nodes.insert(Target::TSSpec, Node { output: ts_spec_path.clone(), inputs, deps });
+
+            // Ranges are computed from the data of each graphic in the spec as it is on disk.
+            let mut inputs = Vec::new();
+            if ts_spec_path.is_file() {
+                for page in ts_spec_from_file(data_root, ts_spec_file)?.pages.iter() {
+                    for series_id in page.graphics().iter().flat_map(|g| g.series_ids.iter()) {
+                        let data_type = page.series_data_type(series_id);
+                        let stage = match series_id.is_transformed() {
+                            true => "transformed_data",
+                            false => "raw_data",
+                        };
+                        let data =
+                            data_path(data_root, stage, data_type, page.country, series_id, "csv");
+                        inputs.push(Input::File(data));
+                    }
+                }
+            }
+            nodes.insert(Target::Ranges, Node {
+                output: ts_spec_path,
+                inputs,
+                deps:   vec!(Target::TSSpec),
+            });
+            page_deps.push(Target::Ranges);
+        }
+
+        let out_dir = match &options.out_dir {
+            Some(out_dir) if TSPageSpec.dir(data_root).is_ok() => out_dir,
+            _ => return Ok(BuildGraph { nodes, pages: Vec::new() }),
+        };
+
+        let templates: Vec<Input> = match TSHtmlTemplate.dir(data_root) {
+            Ok(_) => TSHtmlTemplate.into_resources(data_root)?.iter().map(Input::File).collect(),
+            Err(_) => Vec::new(),
+        };
+
+        let pages = ts_spec_from_resources(data_root)?.pages;
+        for page in pages.iter() {
+            let mut inputs = vec!(Input::Value(page.keytree().to_string()));
+            inputs.extend(templates.iter().cloned());
+            let mut deps = page_deps.clone();
+
+            for (data_type, country, series_id) in page.series_keys() {
+                let stem = series_id.stem();
+                let raw = data_path(data_root, "raw_data", data_type, country, &stem, "csv");
+                let meta = data_path(data_root, "raw_data", data_type, country, &stem, "meta");
+
+                let data = match series_id.is_transformed() {
+                    true => {
+                        let transformed = data_path(
+                            data_root,
+                            "transformed_data",
+                            data_type,
+                            country,
+                            &series_id,
+                            "csv",
+                        );
+                        let target = Target::Transformed(data_type, country, series_id.clone());
+                        nodes.entry(target.clone()).or_insert_with(|| Node {
+                            output: transformed.clone(),
+                            inputs: vec!(Input::File(transformed.clone())),
+                            deps:   Vec::new(),
+                        });
+                        deps.push(target);
+                        deps.push(Target::Raw(data_type, country, stem.clone()));
+                        transformed
+                    },
+                    false => {
+                        deps.push(Target::Raw(data_type, country, series_id.clone()));
+                        raw
+                    },
+                };
+                inputs.push(Input::File(data));
+                inputs.push(Input::File(meta));
+                deps.push(Target::Meta(data_type, country, stem));
+            }
+
+            nodes.insert(Target::Page(page.data_type, page.country, page.index), Node {
+                output: out_dir.join(page_path(page.data_type, page.country, page.index)),
+                inputs,
+                deps,
+            });
+        }
+        Ok(BuildGraph { nodes, pages })
+    }
+
+    /// Return the targets to build in order, with the reason each is planned.
+    pub fn plan(&self, record: &BuildRecord) -> Result<Vec<Step>> {
+        let mut planned: BTreeSet<&Target> = BTreeSet::new();
+        let mut acc = Vec::new();
+
+        for (target, node) in self.nodes.iter() {
+            // Transformed data is not built, so it is never upstream of other targets.
+            let upstream = |dep: &&Target| {
+                planned.contains(dep) && !matches!(dep, Target::Transformed(..))
+            };
+            let reason = if !node.output.exists() {
+                Some(Reason::Missing)
+            } else if let Some(dep) = node.deps.iter().find(upstream) {
+                Some(Reason::Upstream(dep.clone()))
+            } else {
+                match record.get(target) {
+                    None => Some(Reason::New),
+                    Some(hash) if hash != node.hash()? => Some(Reason::Changed),
+                    Some(_) => None,
+                }
+            };
+            if let Some(reason) = reason {
+                planned.insert(target);
+                acc.push(Step { target: target.clone(), reason });
+            }
+        }
+        Ok(acc)
+    }
+
+    fn page(&self, data_type: DataType, country: Country, index: usize) -> Option<&PageSpec> {
+        self.pages.iter().find(|page| {
+            page.data_type == data_type && page.country == country && page.index == index
+        })
+    }
+}
+
+// The path of a data file, such as `raw_data/u/australia/AUSURAMS.csv`.
+fn data_path(
+    data_root: &Path,
+    stage: &str,
+    data_type: DataType,
+    country: Country,
+    series_id: &SeriesId,
+    extension: &str) -> PathBuf
+{
+    data_root
+        .join(stage)
+        .join(data_type.to_string())
+        .join(country.as_filepath())
+        .join(series_id.to_string())
+        .with_extension(extension)
+}
+
+// === Step =======================================================================================
+
+/// Why a target is planned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    Missing,
+    New,
+    Changed,
+    Upstream(Target),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Missing => write!(f, "output missing"),
+            Reason::New => write!(f, "not built before"),
+            Reason::Changed => write!(f, "inputs changed"),
+            Reason::Upstream(target) => write!(f, "after {}", target),
+        }
+    }
+}
+
+impl Serialize for Reason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A target to build.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Step {
+    pub target: Target,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.target, self.reason)
+    }
+}
+
+// === BuildRecord ================================================================================
+
+/// The hash of the inputs of each target when it was last built, by the name of the target.
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildRecord(BTreeMap<String, String>);
+
+impl BuildRecord {
+
+    /// Read `build.keytree` from the data root, or start an empty record if there is none.
+    pub fn from_file<P: AsRef<Path>>(data_root: P) -> Result<Self> {
+        let path = from_path_arg(data_root).join(BUILD_FILE);
+        if !path.is_file() {
+            return Ok(BuildRecord::default())
+        }
+        let entries: Vec<RecordEntry> = KeyTree::parse(&path)?.opt_vec_at("build::target")?;
+        Ok(BuildRecord(entries.into_iter().map(|entry| (entry.name, entry.inputs)).collect()))
+    }
+
+    /// Write `build.keytree` to the data root.
+    pub fn save<P: AsRef<Path>>(&self, data_root: P) -> Result<()> {
+        let path = from_path_arg(data_root).join(BUILD_FILE);
+        fs::write(&path, self.keytree().to_string())
+            .map_err(|_| anyhow!("Failed to write '{}'", path.display()).into())
+    }
+
+    pub fn get(&self, target: &Target) -> Option<&String> {
+        self.0.get(&target.to_string())
+    }
+
+    pub fn insert(&mut self, target: &Target, hash: String) {
+        self.0.insert(target.to_string(), hash);
+    }
+}
+
+impl IntoKeyTree for BuildRecord {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "build");
+        for (name, inputs) in self.0.iter() {
+            kt.push_key(1, "target");
+            kt.push_keyvalue(2, "name", name);
+            kt.push_keyvalue(2, "inputs", inputs);
+        }
+        kt
+    }
+}
+
+// A target in `build.keytree`.
+struct RecordEntry {
+    name:   String,
+    inputs: String,
+}
+
+impl TryInto<RecordEntry> for KeyTree {
+    type Error = KeyTreeError;
+
+    fn try_into(self) -> std::result::Result<RecordEntry, Self::Error> {
+        Ok(
+            RecordEntry {
+                name:   self.from_str("target::name")?,
+                inputs: self.from_str("target::inputs")?,
+            }
+        )
+    }
+}
+
+// === Building ===================================================================================
+
+/// What a build planned and did.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    pub planned:    Vec<Step>,
+    pub built:      Vec<Target>,
+
+    /// Targets which this crate cannot build, which changed on disk and are used as they are.
+    pub stale:      Vec<Target>,
+
+    /// Targets which could not be built, and the targets depending on them.
+    pub skipped:    Vec<Target>,
+
+    /// The targets which failed to build, as `target: error`.
+    pub failed:     Vec<String>,
+}
+
+/// Build every planned target of a data root in order, recording each target as it is built. With
+/// `dry_run` only the plan is returned.
+pub fn build<P: AsRef<Path>>(data_root: P, options: &BuildOptions) -> Result<BuildReport> {
+    let root: PathBuf = from_path_arg(data_root);
+    let mut record = BuildRecord::from_file(&root)?;
+    let mut report = BuildReport::default();
+
+    let mut graph = BuildGraph::new(&root, options)?;
+    report.planned = graph.plan(&record)?;
+    if options.dry_run {
+        return Ok(report)
+    }
+
+    let client = FredClient::new(options.fred.clone());
+
+    // The series specification decides the other targets, so build it first and plan again.
+    let spec_planned = report.planned.iter().any(|step| step.target == Target::SeriesSpec);
+    if let (true, Some(filter_spec)) = (spec_planned, &options.filter_spec) {
+        let node = &graph.nodes[&Target::SeriesSpec];
+        let batch = select_series(filter_spec, &root, &client, options.workers)?;
+        if !batch.is_ok() {
+            return Err(Error::DataSource(batch.failures().join("\n")))
+        }
+        let selections: Vec<Selection> = batch.values().flatten().cloned().collect();
+        fs::write(&node.output, series_spec_from_selections(&selections).keytree().to_string())?;
+        record.insert(&Target::SeriesSpec, node.hash()?);
+        record.save(&root)?;
+        report.built.push(Target::SeriesSpec);
+        graph = BuildGraph::new(&root, options)?;
+    }
+
+    // The ts spec decides the pages, so build the data and specs first and plan the pages again.
+    let mut specs_built = false;
+    for step in graph.plan(&record)? {
+        if matches!(step.target, Target::SeriesSpec | Target::Page(..)) { continue }
+        let node = &graph.nodes[&step.target];
+        if node.deps.iter().any(|dep| report.skipped.contains(dep)) {
+            report.skipped.push(step.target);
+            continue
+        }
+        if let Target::Transformed(..) = step.target {
+            match node.output.exists() {
+                true => {
+                    record.insert(&step.target, node.hash()?);
+                    record.save(&root)?;
+                    report.stale.push(step.target);
+                },
+                false => report.skipped.push(step.target),
+            }
+            continue
+        }
+        match build_target(&root, options, &client, &step.target) {
+            Ok(()) => {
+                record.insert(&step.target, node.hash()?);
+                record.save(&root)?;
+                specs_built |= matches!(step.target, Target::TSSpec | Target::Ranges);
+                report.built.push(step.target);
+            },
+            Err(e) => {
+                report.failed.push(format!("{}: {}", step.target, e));
+                report.skipped.push(step.target);
+            },
+        }
+    }
+    if specs_built {
+        graph = BuildGraph::new(&root, options)?;
+
+        // The inputs of the ranges are read from the ts spec, which may have just been written.
+        let ranges_built = report.built.contains(&Target::Ranges);
+        if let (true, Some(node)) = (ranges_built, graph.nodes.get(&Target::Ranges)) {
+            record.insert(&Target::Ranges, node.hash()?);
+            record.save(&root)?;
+        }
+    }
+
+    let mut pages: Option<(Templates, RenderOptions)> = None;
+    let mut series_written = BTreeSet::new();
+
+    for step in graph.plan(&record)? {
+        let (data_type, country, index, out_dir) = match (&step.target, &options.out_dir) {
+            (Target::Page(data_type, country, index), Some(out_dir)) => {
+                (*data_type, *country, *index, out_dir)
+            },
+            _ => continue,
+        };
+        let node = &graph.nodes[&step.target];
+        if node.deps.iter().any(|dep| report.skipped.contains(dep)) {
+            report.skipped.push(step.target);
+            continue
+        }
+
+        let page = graph
+            .page(data_type, country, index)
+            .ok_or(anyhow!("No page for [{}]", step.target))?;
+        if pages.is_none() {
+            pages = Some((
+                Templates::new(&root)?,
+                RenderOptions {
+                    svg_root: Some(root.clone()),
+                    ..RenderOptions::new(&root, "../../", SeriesDataMode::Linked)?
+                },
+            ));
+        }
+        let (templates, render_options) = pages.as_mut().unwrap();
+        match build_page(&root, out_dir, page, templates, render_options, &mut series_written) {
+            Ok(()) => {
+                record.insert(&step.target, node.hash()?);
+                record.save(&root)?;
+                report.built.push(step.target);
+            },
+            Err(e) => {
+                report.failed.push(format!("{}: {}", step.target, e));
+                report.skipped.push(step.target);
+            },
+        }
+    }
+
+    // Pages which were skipped are left out of the indexes, so that the site has no broken links.
+    if let (Some((templates, _)), Some(out_dir)) = (&pages, &options.out_dir) {
+        let built: Vec<&PageSpec> = graph.pages
+            .iter()
+            .filter(|page| {
+                let target = Target::Page(page.data_type, page.country, page.index);
+                !report.skipped.contains(&target)
+            })
+            .collect();
+        export_indexes(out_dir, templates, &built)?;
+        export_assets(&root, out_dir)?;
+    }
+    Ok(report)
+}
+
+// Write the series of a page which have not been written yet, then the page.
+fn build_page(
+    root: &Path,
+    out_dir: &Path,
+    page: &PageSpec,
+    templates: &Templates,
+    options: &mut RenderOptions,
+    series_written: &mut BTreeSet<SeriesKey>) -> Result<()>
+{
+    for key in page.series_keys() {
+        if !series_written.contains(&key) {
+            export_series(root, out_dir, &key)?;
+            series_written.insert(key);
+        }
+    }
+    export_page(root, out_dir, page, templates, options)?;
+    Ok(())
+}
+
+// Build a target of the data or the specs from its inputs.
+fn build_target(
+    root: &Path,
+    options: &BuildOptions,
+    client: &FredClient,
+    target: &Target) -> Result<()>
+{
+    let ts_spec = || {
+        options.ts_spec
+            .as_ref()
+            .ok_or_else(|| Error::Config(format!("No ts spec to build [{}]", target)))
+    };
+    match target {
+        Target::Raw(data_type, country, series_id) => {
+            let series_spec = SeriesSpec::new(*data_type, *country, series_id.clone());
+            fetch_series(root, &series_spec, client)?;
+        },
+        Target::Meta(data_type, country, series_id) => {
+            let series_spec = SeriesSpec::new(*data_type, *country, series_id.clone());
+            write_meta_file(root, &series_spec, client)?;
+        },
+        Target::TSSpec => {
+            generate_ts_spec_file(root, Path::new(&options.series_spec), ts_spec()?, true)?;
+        },
+        Target::Ranges => {
+            write_ranges(root, ts_spec()?)?;
+        },
+        _ => return Err(anyhow!("[{}] cannot be built on its own", target).into()),
+    }
+    Ok(())
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::fred_client::FRED_BASE_URL;
+    use crate::fred_client::cache::{ResponseCache, DEFAULT_TTL};
+    use crate::workers::DEFAULT_WORKERS;
+
+    fn options() -> BuildOptions {
+        BuildOptions {
+            series_spec:    "series_spec.keytree".to_string(),
+            filter_spec:    None,
+            ts_spec:        None,
+            out_dir:        None,
+            dry_run:        false,
+            workers:        DEFAULT_WORKERS,
+            fred:           FredConfig::default(),
+        }
+    }
+
+    fn targets(steps: &[Step]) -> Vec<String> {
+        steps.iter().map(|step| step.to_string()).collect()
+    }
+
+    #[test]
+    fn only_changed_targets_should_be_planned() {
+        let root = std::env::temp_dir().join("graphics_pipeline_build");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("specs")).unwrap();
+        fs::create_dir_all(root.join("raw_data/u/australia")).unwrap();
+        fs::create_dir_all(root.join("ts_graphics/spec")).unwrap();
+
+        fs::write(root.join("specs/series_spec.keytree"), "seriess:
+    series:
+        data_type:  u
+        country:    Australia
+        series_id:  AUSURAMS
+").unwrap();
+        let csv = root.join("raw_data/u/australia/AUSURAMS.csv");
+        fs::write(&csv, "2000-01-01,5\n").unwrap();
+
+        // FRED is only read from the cache.
+        let cache = ResponseCache::new(root.join("cache"), DEFAULT_TTL, true);
+        let params = [("series_id", "AUSURAMS")];
+        cache.write(
+            &cache.file(FRED_BASE_URL, "series/observations", &params),
+            r#"{"observations": [{"date": "2000-01-01", "value": "5"}]}"#,
+        ).unwrap();
+        cache.write(&cache.file(FRED_BASE_URL, "series", &params), r#"{"seriess": [{
+            "id": "AUSURAMS",
+            "title": "Unemployment Rate",
+            "realtime_start": "2021-06-03",
+            "observation_start": "2000-01-01",
+            "observation_end": "2000-01-01",
+            "frequency": "Monthly",
+            "seasonal_adjustment": "Seasonally Adjusted"
+        }]}"#).unwrap();
+        let options = BuildOptions {
+            fred: FredConfig { cache: Some(cache), ..FredConfig::default() },
+            ..options()
+        };
+
+        let plan = |record: &BuildRecord, options: &BuildOptions| {
+            let root = from_path_arg(&root);
+            targets(&BuildGraph::new(&root, options).unwrap().plan(record).unwrap())
+        };
+        assert_eq!(
+            plan(&BuildRecord::default(), &options),
+            vec!(
+                "raw u australia AUSURAMS (not built before)",
+                "meta u australia AUSURAMS (output missing)",
+            ),
+        );
+
+        let report = build(&root, &options).unwrap();
+        let raw = Target::Raw(DataType::U, Country::Australia, SeriesId::new("AUSURAMS"));
+        let meta = Target::Meta(DataType::U, Country::Australia, SeriesId::new("AUSURAMS"));
+        assert_eq!(report.built, vec!(raw, meta));
+        assert!(report.failed.is_empty());
+
+        let record = BuildRecord::from_file(&root).unwrap();
+        assert!(plan(&record, &options).is_empty());
+
+        fs::write(&csv, "2000-01-01,6\n").unwrap();
+        assert_eq!(plan(&record, &options), vec!("meta u australia AUSURAMS (inputs changed)"));
+        build(&root, &options).unwrap();
+
+        // The ts spec and its ranges are only targets when asked for.
+        let options = BuildOptions { ts_spec: Some("ts_page_spec.keytree".to_string()), ..options };
+        let record = BuildRecord::from_file(&root).unwrap();
+        assert_eq!(
+            plan(&record, &options),
+            vec!("ts_spec (output missing)", "ranges (output missing)"),
+        );
+        let report = build(&root, &options).unwrap();
+        assert_eq!(report.built, vec!(Target::TSSpec, Target::Ranges));
+        let record = BuildRecord::from_file(&root).unwrap();
+        assert!(plan(&record, &options).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn pages_should_be_planned_when_transformed_data_changes_on_disk() {
+        let root = std::env::temp_dir()
+            .join(format!("graphics_pipeline_build_transformed_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in [
+            "specs",
+            "raw_data/u/australia",
+            "transformed_data/u/australia",
+            "ts_graphics/spec",
+            "out/u/australia",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("specs/series_spec.keytree"), "seriess:
+    series:
+        data_type:  u
+        country:    Australia
+        series_id:  AUSURAMS
+").unwrap();
+        fs::write(root.join("ts_graphics/spec/ts_page_spec.keytree"), "ts_spec:
+    page:
+        country:        Australia
+        data_type:      u
+        index:          0
+
+        series:
+            data_type:  u
+            series_id:  AUSURAMS
+
+        graphic:
+            category:   cleaned
+            series_id:  AUSURAMS_a
+").unwrap();
+        fs::write(root.join("raw_data/u/australia/AUSURAMS.csv"), "2000-01-01,5\n").unwrap();
+        fs::write(root.join("raw_data/u/australia/AUSURAMS.meta"), "").unwrap();
+        let transformed = root.join("transformed_data/u/australia/AUSURAMS_a.csv");
+        fs::write(&transformed, "2000-01-01,5\n").unwrap();
+        fs::write(root.join("out/u/australia/0.html"), "").unwrap();
+
+        let options = BuildOptions { out_dir: Some(root.join("out")), ..options() };
+        let graph = BuildGraph::new(&root, &options).unwrap();
+
+        // Every target as it would be recorded by a build, including the transformed data.
+        let mut record = BuildRecord::default();
+        for (target, node) in graph.nodes.iter() {
+            record.insert(target, node.hash().unwrap());
+        }
+        assert!(graph.plan(&record).unwrap().is_empty());
+
+        fs::write(&transformed, "2000-01-01,6\n").unwrap();
+        assert_eq!(
+            targets(&graph.plan(&record).unwrap()),
+            vec!(
+                "transformed u australia AUSURAMS_a (inputs changed)",
+                "page u australia 0 (inputs changed)",
+            ),
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,600 @@
+//! The `gp` command-line tool, which runs each stage of the pipeline over a data root.
+//!
+//! ```text
+//! gp <command> [data_root] [--config <file>] [--series-spec <file>] [--ts-spec <file>]
+//!     [--workers <n>] [--json] [options]
+//!
+//! filter      select series from FRED [--filter-spec <file>] [--write] [--budget <requests>]
+//!                                                         [--offline]
+//! fetch       download the CSV data of the series spec
+//!                                             [--budget <requests>] [--offline]
+//! meta        download the `.meta` files of the series spec
+//!                                             [--budget <requests>] [--offline]
+//! verify      check raw data, drift, page specs and manifest  [--prune]
+//! transform   write `transformed_data` from `raw_data`
+//! build       rebuild what is stale      [--filter-spec <file>] [--out <dir>] [--dry-run]
+//!                                             [--budget <requests>] [--offline] [--generate]
+//! export      write the static site                           --out <dir>
+//! serve       serve the pages             [--bind <address>] [--port <port>] [--watch]
+//! ```
+//! With `--json` the report of a command, or its error, is printed as JSON for scripts. Series
+//! are worked on `--workers` buckets at a time. Responses from FRED are cached under `cache/` in
+//! the data root for a day, and with `--offline` only cached responses are used. A build with
+//! `--generate` also regenerates the `--ts-spec` file from the series spec, with its ranges.
+//!
+//! The data root, `--workers`, `--bind` and `--port` override the
+//! [`PipelineConfig`](../config/struct.PipelineConfig.html) from `--config` or `GP_CONFIG` and the
+//! environment, so the data root may be left out if it is configured.
+
+use crate::{
+    build::{build, BuildOptions, BuildReport},
+    data_transforms::save_transforms,
+    error::{Error, Result},
+    drift::{find_file_drift, find_ts_spec_drift, prune_files},
+    export::{export_site, ExportSummary},
+    config::{ConfigLayer, PipelineConfig},
+    file_resources::IntoResources,
+    file_resources::impls::{Spec, TSPageSpec},
+    filter_to_series::{select_series, series_spec_from_selections, Selection},
+    fred_client::{FredClient, FredConfig},
+    manifest::{Manifest, MANIFEST_FILE},
+    series_to_disk::{fetch_raw, raw_status, spec_map_from_spec},
+    series_to_meta::overwrite_meta_files,
+    server::{self, DEFAULT_SERIES_SPEC},
+    ts_graphics::ts_spec::ts_spec_from_file,
+};
+use key_tree::serialize::IntoKeyTree;
+use serde::Serialize;
+use std::{fmt, fs, path::PathBuf};
+
+pub const DEFAULT_FILTER_SPEC: &str = "filter_spec.keytree";
+pub const DEFAULT_TS_SPEC: &str = "ts_page_spec.keytree";
+
+pub const USAGE: &str = "Usage: gp <filter|fetch|meta|verify|transform|build|export|serve> [data_root] [options] [--json]";
+
+// === Cli ========================================================================================
+
+/// A stage of the pipeline, with its options.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Filter { filter_spec: String, write: bool },
+    Fetch,
+    Meta,
+    Verify { prune: bool },
+    Transform,
+    Build {
+        filter_spec:    Option<String>,
+
+        /// Regenerate the `TSSpec` file and its ranges.
+        generate:       bool,
+        out_dir:        Option<PathBuf>,
+        dry_run:        bool,
+    },
+    Export { out_dir: PathBuf },
+    Serve { bind: Option<String>, port: Option<u16>, watch: bool },
+}
+
+/// A command and the data root and specs it runs on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cli {
+    pub command:        Command,
+    pub data_root:      Option<PathBuf>,
+
+    /// The `PipelineConfig` file.
+    pub config:         Option<PathBuf>,
+
+    /// The series specification in the `specs` directory.
+    pub series_spec:    String,
+
+    /// The `TSSpec` file in the `ts_graphics/spec` directory.
+    pub ts_spec:        String,
+
+    /// The number of buckets of series worked on at once.
+    pub workers:        Option<usize>,
+
+    /// The most requests made to FRED, counting retries.
+    pub budget:         Option<u32>,
+
+    /// Serve FRED responses only from the cache.
+    pub offline:        bool,
+
+    /// Print the report as JSON.
+    pub json:           bool,
+}
+
+impl Cli {
+
+    /// Read the command from command-line arguments, excluding the program name.
+    /// ```
+    /// # use graphics_pipeline::cli::{Cli, Command};
+    /// let args = vec!("verify", "../../shared_data", "--prune", "--json");
+    /// let cli = Cli::from_args(args.into_iter().map(String::from)).unwrap();
+    /// assert_eq!(cli.command, Command::Verify { prune: true });
+    /// assert!(cli.json);
+    /// ```
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self> {
+        let name = args.next().ok_or_else(|| Error::Config(USAGE.to_string()))?;
+
+        let mut data_root = None;
+        let mut config = None;
+        let mut series_spec = DEFAULT_SERIES_SPEC.to_string();
+        let mut ts_spec = DEFAULT_TS_SPEC.to_string();
+        let mut workers = None;
+        let mut budget = None;
+        let mut offline = false;
+        let mut json = false;
+
+        let mut filter_spec = None;
+        let mut write = false;
+        let mut prune = false;
+        let mut dry_run = false;
+        let mut generate = false;
+        let mut out_dir = None;
+        let mut bind = None;
+        let mut port = None;
+        let mut watch = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next().ok_or_else(|| Error::Config(format!("{} requires a value", arg)))
+            };
+            match (name.as_str(), arg.as_str()) {
+                (_, "--json") => json = true,
+                (_, "--config") => config = Some(PathBuf::from(value()?)),
+                (_, "--series-spec") => series_spec = value()?,
+                (_, "--ts-spec") => ts_spec = value()?,
+                (_, "--workers") => {
+                    let s = value()?;
+                    workers = match s.parse() {
+                        Ok(n) if n > 0 => Some(n),
+                        _ => return Err(Error::Config(format!("Failed to parse workers [{}]", s))),
+                    };
+                },
+                ("filter" | "build", "--filter-spec") => filter_spec = Some(value()?),
+                ("filter", "--write") => write = true,
+                ("filter" | "fetch" | "meta" | "build", "--budget") => {
+                    let s = value()?;
+                    let n = s
+                        .parse()
+                        .map_err(|_| Error::Config(format!("Failed to parse budget [{}]", s)))?;
+                    budget = Some(n);
+                },
+                ("filter" | "fetch" | "meta" | "build", "--offline") => offline = true,
+                ("verify", "--prune") => prune = true,
+                ("build", "--dry-run") => dry_run = true,
+                ("build", "--generate") => generate = true,
+                ("build" | "export", "--out") => out_dir = Some(PathBuf::from(value()?)),
+                ("serve", "--bind") => bind = Some(value()?),
+                ("serve", "--port") => {
+                    let s = value()?;
+                    let n = s
+                        .parse()
+                        .map_err(|_| Error::Config(format!("Failed to parse port [{}]", s)))?;
+                    port = Some(n);
+                },
+                ("serve", "--watch") => watch = true,
+                _ if arg.starts_with("--") => {
+                    return Err(Error::Config(format!("Unknown option [{}] for {}", arg, name)))
+                },
+                _ => {
+                    if data_root.is_some() {
+                        return Err(Error::Config(format!("Unexpected argument [{}]", arg)))
+                    }
+                    data_root = Some(PathBuf::from(arg));
+                },
+            }
+        }
+
+        let command = match name.as_str() {
+            "filter" => Command::Filter {
+                filter_spec: filter_spec.unwrap_or_else(|| DEFAULT_FILTER_SPEC.to_string()),
+                write,
+            },
+            "fetch" => Command::Fetch,
+            "meta" => Command::Meta,
+            "verify" => Command::Verify { prune },
+            "transform" => Command::Transform,
+            "build" => Command::Build { filter_spec, generate, out_dir, dry_run },
+            "export" => Command::Export {
+                out_dir: out_dir
+                    .ok_or_else(|| Error::Config("export requires --out <dir>".to_string()))?,
+            },
+            "serve" => Command::Serve { bind, port, watch },
+            _ => return Err(Error::Config(format!("Unknown command [{}]\n{}", name, USAGE))),
+        };
+
+        Ok(
+            Cli {
+                command,
+                data_root,
+                config,
+                series_spec,
+                ts_spec,
+                workers,
+                budget,
+                offline,
+                json,
+            }
+        )
+    }
+
+    /// Load and check the configuration, overridden by the arguments of the command.
+    pub fn config(&self) -> Result<PipelineConfig> {
+        let (bind, port) = match &self.command {
+            Command::Serve { bind, port, .. } => (bind.clone(), *port),
+            _ => (None, None),
+        };
+        let args = ConfigLayer {
+            data_root:  self.data_root.clone(),
+            bind,
+            port,
+            workers:    self.workers,
+            ..ConfigLayer::default()
+        };
+        PipelineConfig::load(self.config.as_deref(), args)
+    }
+
+    /// The FRED client settings of the command.
+    pub fn fred_config(&self, config: &PipelineConfig) -> FredConfig {
+        FredConfig { budget: self.budget, ..config.fred_config(self.offline) }
+    }
+
+    /// Run the command, returning what it found or wrote.
+    pub fn run(&self) -> Result<Report> {
+        let config = self.config()?;
+        let root: PathBuf = config.data_root.clone();
+
+        match &self.command {
+            Command::Filter { filter_spec, write } => {
+                let client = FredClient::new(self.fred_config(&config));
+                let batch = select_series(filter_spec, &root, &client, config.workers)?;
+                let selections: Vec<Selection> = batch.values().flatten().cloned().collect();
+                let failed = batch.failures();
+                let written = match write {
+                    true if !batch.is_ok() => {
+                        return Err(Error::DataSource(failed.join("\n")))
+                    },
+                    true => {
+                        let path = Spec.dir(&root)?.join(&self.series_spec);
+                        let seriess = series_spec_from_selections(&selections);
+                        fs::write(&path, seriess.keytree().to_string())?;
+                        Some(path)
+                    },
+                    false => None,
+                };
+                Ok(Report::Filter { selections, failed, written })
+            },
+            Command::Fetch => {
+                let client = FredClient::new(self.fred_config(&config));
+                let batch = fetch_raw(&root, &self.series_spec, &client, config.workers)?;
+                Ok(Report::Fetch {
+                    written: batch.values().cloned().collect(),
+                    failed: batch.failures(),
+                })
+            },
+            Command::Meta => {
+                let client = FredClient::new(self.fred_config(&config));
+                let batch =
+                    overwrite_meta_files(&root, &self.series_spec, &client, config.workers)?;
+                Ok(Report::Meta {
+                    written: batch.values().cloned().collect(),
+                    failed: batch.failures(),
+                })
+            },
+            Command::Transform => {
+                let batch = save_transforms(&root, &self.series_spec, config.workers)?;
+                Ok(Report::Transform {
+                    written: batch.values().cloned().collect(),
+                    failed: batch.failures(),
+                })
+            },
+            Command::Verify { prune } => {
+                let batch = raw_status(&root, &self.series_spec, config.workers)?;
+                let missing_csv = batch
+                    .values()
+                    .filter_map(|(file, found)| if *found { None } else { Some(file.clone()) })
+                    .collect();
+
+                let spec_map = spec_map_from_spec(&root, &self.series_spec)?;
+                let mut drifts = find_file_drift(&root, &spec_map)?;
+                let mut violations = Vec::new();
+                if TSPageSpec.has_file(&root, &self.ts_spec).unwrap_or(false) {
+                    let ts_spec = ts_spec_from_file(&root, &self.ts_spec)?;
+                    drifts.extend(find_ts_spec_drift(&ts_spec, &spec_map));
+                    violations = ts_spec.validate();
+                }
+
+                let manifest = match root.join(MANIFEST_FILE).is_file() {
+                    true => Manifest::from_file(&root)?.verify(&root, &self.series_spec)?,
+                    false => Vec::new(),
+                };
+
+                let pruned = match prune {
+                    true => prune_files(&drifts)?,
+                    false => Vec::new(),
+                };
+
+                Ok(Report::Verify {
+                    missing_csv,
+                    failed: batch.failures(),
+                    drift: drifts.iter().map(|drift| drift.to_string()).collect(),
+                    violations: violations.iter().map(|violation| violation.to_string()).collect(),
+                    manifest: manifest.iter().map(|issue| issue.to_string()).collect(),
+                    pruned,
+                })
+            },
+            Command::Build { filter_spec, generate, out_dir, dry_run } => {
+                let options = BuildOptions {
+                    series_spec:    self.series_spec.clone(),
+                    filter_spec:    filter_spec.clone(),
+                    ts_spec:        generate.then(|| self.ts_spec.clone()),
+                    out_dir:        out_dir.clone(),
+                    dry_run:        *dry_run,
+                    workers:        config.workers,
+                    fred:           self.fred_config(&config),
+                };
+                Ok(Report::Build(build(&root, &options)?))
+            },
+            Command::Export { out_dir } => {
+                Ok(Report::Export(export_site(&root, out_dir, config.workers)?))
+            },
+            Command::Serve { watch, .. } => {
+                let server_config = config.server_config(&self.series_spec, *watch);
+                actix_web::rt::System::new().block_on(server::run(server_config))?;
+                Ok(Report::Served)
+            },
+        }
+    }
+}
+
+impl Command {
+
+    /// The name of the command on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Filter { .. } => "filter",
+            Command::Fetch => "fetch",
+            Command::Meta => "meta",
+            Command::Verify { .. } => "verify",
+            Command::Transform => "transform",
+            Command::Build { .. } => "build",
+            Command::Export { .. } => "export",
+            Command::Serve { .. } => "serve",
+        }
+    }
+}
+
+// === Report =====================================================================================
+
+/// What a command found or wrote.
+#[derive(Debug, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Report {
+    Filter {
+        selections: Vec<Selection>,
+
+        /// The tag selectors which failed, as `tag: error`.
+        failed:     Vec<String>,
+
+        /// The series specification, if it was written.
+        written:    Option<PathBuf>,
+    },
+    Fetch {
+        written:    Vec<PathBuf>,
+
+        /// The series which could not be downloaded, as `series_id: error`.
+        failed:     Vec<String>,
+    },
+    Meta {
+        written:    Vec<PathBuf>,
+        failed:     Vec<String>,
+    },
+    Transform {
+        written:    Vec<PathBuf>,
+        failed:     Vec<String>,
+    },
+    Verify {
+        missing_csv:    Vec<PathBuf>,
+
+        /// The series which could not be checked, as `series_id: error`.
+        failed:         Vec<String>,
+        drift:          Vec<String>,
+
+        /// The rules broken by the `TSSpec`, as `ts_spec::page[0]::graphic[1]: message`.
+        violations:     Vec<String>,
+        manifest:       Vec<String>,
+        pruned:         Vec<PathBuf>,
+    },
+    Build(BuildReport),
+    Export(ExportSummary),
+    Served,
+}
+
+impl Report {
+
+    /// Whether everything the command worked on succeeded, and `verify` found no issues. `gp`
+    /// exits with an error otherwise.
+    /// ```
+    /// # use graphics_pipeline::cli::Report;
+    /// # use graphics_pipeline::export::ExportSummary;
+    /// let failed = vec!("u/australia/0.html: No data for series [AUSURAMS]".to_string());
+    /// assert!(!Report::Export(ExportSummary { failed, ..ExportSummary::default() }).is_ok());
+    /// ```
+    pub fn is_ok(&self) -> bool {
+        match self {
+            Report::Filter { failed, .. }
+            | Report::Fetch { failed, .. }
+            | Report::Meta { failed, .. }
+            | Report::Transform { failed, .. } => failed.is_empty(),
+            Report::Verify { missing_csv, failed, drift, violations, manifest, .. } => {
+                missing_csv.is_empty() &&
+                failed.is_empty() &&
+                drift.is_empty() &&
+                violations.is_empty() &&
+                manifest.is_empty()
+            },
+            Report::Build(report) => report.failed.is_empty(),
+            Report::Export(summary) => summary.failed.is_empty(),
+            Report::Served => true,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Report::Filter { selections, failed, written } => {
+                for selection in selections {
+                    let mark = if selection.selected { "     " } else { "drop:" };
+                    writeln!(
+                        f,
+                        "{} {} {} {} {}",
+                        mark,
+                        selection.country,
+                        selection.data_type,
+                        selection.series_id,
+                        selection.title,
+                    )?;
+                }
+                for failure in failed {
+                    writeln!(f, "fail: {}", failure)?;
+                }
+                if let Some(path) = written {
+                    writeln!(f, "wrote {}", path.display())?;
+                }
+            },
+            Report::Fetch { written, failed }
+            | Report::Meta { written, failed }
+            | Report::Transform { written, failed } => {
+                for path in written {
+                    writeln!(f, "wrote {}", path.display())?;
+                }
+                for failure in failed {
+                    writeln!(f, "fail  {}", failure)?;
+                }
+            },
+            Report::Verify { missing_csv, failed, drift, violations, manifest, pruned } => {
+                for file in missing_csv {
+                    writeln!(f, "none {}", file.display())?;
+                }
+                for failure in failed {
+                    writeln!(f, "fail {}", failure)?;
+                }
+                for line in drift.iter().chain(manifest.iter()) {
+                    writeln!(f, "{}", line)?;
+                }
+                for violation in violations {
+                    writeln!(f, "invalid {}", violation)?;
+                }
+                for path in pruned {
+                    writeln!(f, "pruned {}", path.display())?;
+                }
+                let issues = missing_csv.len()
+                    + failed.len()
+                    + drift.len()
+                    + violations.len()
+                    + manifest.len();
+                if issues == 0 {
+                    writeln!(f, " ok  no issues")?;
+                }
+            },
+            Report::Build(report) => {
+                if report.planned.is_empty() {
+                    writeln!(f, " ok  up to date")?;
+                }
+                // A dry run only plans.
+                if report.built.is_empty() && report.stale.is_empty() && report.skipped.is_empty() {
+                    for step in report.planned.iter() {
+                        writeln!(f, "plan    {}", step)?;
+                    }
+                }
+                for target in report.built.iter() {
+                    writeln!(f, "built   {}", target)?;
+                }
+                for target in report.stale.iter() {
+                    writeln!(f, "stale   {}", target)?;
+                }
+                for target in report.skipped.iter() {
+                    writeln!(f, "skipped {}", target)?;
+                }
+                for failure in report.failed.iter() {
+                    writeln!(f, "fail    {}", failure)?;
+                }
+            },
+            Report::Export(summary) => {
+                writeln!(f, "pages   {}", summary.pages)?;
+                writeln!(f, "series  {}", summary.series)?;
+                writeln!(f, "indexes {}", summary.indexes)?;
+                writeln!(f, "images  {}", summary.images)?;
+                writeln!(f, "assets  {}", summary.assets)?;
+                for failure in summary.failed.iter() {
+                    writeln!(f, "fail    {}", failure)?;
+                }
+            },
+            Report::Served => {},
+        }
+        Ok(())
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Result<Cli> {
+        Cli::from_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn options_should_belong_to_their_command() {
+        let serve = cli(&["serve", "data", "--port", "8081", "--watch"]).unwrap();
+        assert_eq!(serve.command, Command::Serve { bind: None, port: Some(8081), watch: true });
+        assert_eq!(serve.data_root, Some(PathBuf::from("data")));
+
+        assert!(cli(&["verify", "data", "--port", "8081"]).is_err());
+        assert!(cli(&["export", "data"]).is_err());
+        assert!(cli(&["unknown", "data"]).is_err());
+
+        assert_eq!(cli(&["verify", "data"]).unwrap().workers, None);
+        assert_eq!(cli(&["filter", "data", "--workers", "8"]).unwrap().workers, Some(8));
+        assert!(cli(&["filter", "data", "--workers", "0"]).is_err());
+        assert_eq!(cli(&["build", "data", "--budget", "50"]).unwrap().budget, Some(50));
+        assert!(cli(&["verify", "data", "--budget", "50"]).is_err());
+        assert!(cli(&["filter", "data", "--offline"]).unwrap().offline);
+        assert!(cli(&["fetch", "data", "--offline"]).unwrap().offline);
+        assert!(cli(&["transform", "data", "--offline"]).is_err());
+    }
+
+    #[test]
+    fn verify_should_fail_on_any_issue() {
+        let verify = |drift: Vec<String>| Report::Verify {
+            missing_csv:    Vec::new(),
+            failed:         Vec::new(),
+            drift,
+            violations:     Vec::new(),
+            manifest:       Vec::new(),
+            pruned:         Vec::new(),
+        };
+        assert!(verify(Vec::new()).is_ok());
+        let drift = "orphan csv   raw_data/u/australia/AUSURANAA.csv".to_string();
+        assert!(!verify(vec!(drift)).is_ok());
+    }
+
+    #[test]
+    fn data_root_may_come_from_the_config() {
+        let verify = cli(&["verify", "--config", "gp.keytree"]).unwrap();
+        assert_eq!(verify.data_root, None);
+        assert_eq!(verify.config, Some(PathBuf::from("gp.keytree")));
+    }
+
+    #[test]
+    fn report_should_serialize_with_its_command() {
+        let report = Report::Export(ExportSummary { pages: 2, ..ExportSummary::default() });
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            r#"{"command":"export","pages":2,"series":0,"indexes":0,"images":0,"assets":0,"failed":[]}"#,
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! The configuration of the pipeline, from a keytree file, the environment and the command line,
+//! each overriding the one before.
+//!
+//! ```text
+//! pipeline:
+//!     data_root:      ../../shared_data
+//!     bind:           127.0.0.1
+//!     port:           8080
+//!     cache_dir:      cache
+//!     cache_ttl:      86400
+//!     workers:        4
+//!     include:        secrets.keytree
+//! ```
+//! Every key is optional. Files may be included as in other specs, so that `fred_api_key` can be
+//! kept in a file outside version control, and a later file overrides an earlier one. A relative
+//! path in a file is relative to the directory of that file, and a relative `cache_dir` from
+//! anywhere else is relative to the data root. The data root is made absolute, so nothing depends
+//! on the current directory once the configuration is loaded.
+//!
+//! | Key            | Environment        | Default                     |
+//! |----------------|--------------------|-----------------------------|
+//! | `data_root`    | `GP_DATA_ROOT`     | none, it must be set        |
+//! | `fred_api_key` | `FRED_API_KEY`     | none                        |
+//! | `bind`         | `GP_BIND`          | `127.0.0.1`                 |
+//! | `port`         | `GP_PORT`          | `8080`                      |
+//! | `cache_dir`    | `GP_CACHE_DIR`     | `cache` in the data root    |
+//! | `cache_ttl`    | `GP_CACHE_TTL`     | a day, in seconds           |
+//! | `workers`      | `GP_WORKERS`       | `4`                         |
+//!
+//! The file is given on the command line or by `GP_CONFIG`.
+
+use crate::{
+    error::{Error, Result},
+    fred_client::{FredConfig, API_KEY_ENV},
+    fred_client::cache::{ResponseCache, CACHE_DIR, DEFAULT_TTL},
+    server::{ServerConfig, DEFAULT_BIND, DEFAULT_PORT},
+    spec_files::load_spec_files,
+    spec_files::diagnostics::{parses, KeyRule},
+    workers::DEFAULT_WORKERS,
+};
+use key_tree::{KeyTree, KeyTreeError};
+use std::{
+    env,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// The environment variable naming the config file.
+pub const CONFIG_ENV: &str = "GP_CONFIG";
+
+pub const CONFIG_SCHEMA: &[KeyRule] = &[
+    KeyRule::block("pipeline", true),
+    KeyRule::value("pipeline::data_root", false, "path", parses::<PathBuf>),
+    KeyRule::value("pipeline::fred_api_key", false, "API key", parses::<String>),
+    KeyRule::value("pipeline::bind", false, "IP address", parses::<IpAddr>),
+    KeyRule::value("pipeline::port", false, "port", parses::<u16>),
+    KeyRule::value("pipeline::cache_dir", false, "path", parses::<PathBuf>),
+    KeyRule::value("pipeline::cache_ttl", false, "seconds", parses::<u64>),
+    KeyRule::value("pipeline::workers", false, "number", parses::<usize>),
+];
+
+// === ConfigLayer ================================================================================
+
+/// The settings from one source, any of which may be unset.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigLayer {
+    pub data_root:      Option<PathBuf>,
+    pub fred_api_key:   Option<String>,
+    pub bind:           Option<String>,
+    pub port:           Option<u16>,
+    pub cache_dir:      Option<PathBuf>,
+
+    /// Seconds.
+    pub cache_ttl:      Option<u64>,
+    pub workers:        Option<usize>,
+}
+
+impl ConfigLayer {
+
+    /// The settings of `self`, overridden by those which are set in `other`.
+    pub fn merge(self, other: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            data_root:      other.data_root.or(self.data_root),
+            fred_api_key:   other.fred_api_key.or(self.fred_api_key),
+            bind:           other.bind.or(self.bind),
+            port:           other.port.or(self.port),
+            cache_dir:      other.cache_dir.or(self.cache_dir),
+            cache_ttl:      other.cache_ttl.or(self.cache_ttl),
+            workers:        other.workers.or(self.workers),
+        }
+    }
+
+    /// Read a config file and the files it includes, resolving relative paths against the
+    /// directory of the file which sets them.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let files: Vec<(PathBuf, ConfigLayer)> =
+            load_spec_files(&[path.as_ref().to_path_buf()], CONFIG_SCHEMA)?;
+
+        Ok(files.into_iter().fold(ConfigLayer::default(), |acc, (file, layer)| {
+            let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+            let resolve = |path: PathBuf| dir.join(path);
+            acc.merge(ConfigLayer {
+                data_root:  layer.data_root.map(resolve),
+                cache_dir:  layer.cache_dir.map(resolve),
+                ..layer
+            })
+        }))
+    }
+
+    /// Read the settings in environment variables, looking each up with `var`.
+    pub fn from_env<F: Fn(&str) -> Option<String>>(var: F) -> Result<Self> {
+        Ok(
+            ConfigLayer {
+                data_root:      var("GP_DATA_ROOT").map(PathBuf::from),
+                fred_api_key:   var(API_KEY_ENV),
+                bind:           var("GP_BIND"),
+                port:           parse_var(&var, "GP_PORT")?,
+                cache_dir:      var("GP_CACHE_DIR").map(PathBuf::from),
+                cache_ttl:      parse_var(&var, "GP_CACHE_TTL")?,
+                workers:        parse_var(&var, "GP_WORKERS")?,
+            }
+        )
+    }
+}
+
+// Parse the environment variable `key`, if it is set.
+fn parse_var<T, F>(var: &F, key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    F: Fn(&str) -> Option<String>,
+{
+    match var(key) {
+        Some(s) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Config(format!("{} [{}] is not valid", key, s))),
+        None => Ok(None),
+    }
+}
+
+impl TryInto<ConfigLayer> for KeyTree {
+    type Error = KeyTreeError;
+
+    fn try_into(self) -> std::result::Result<ConfigLayer, Self::Error> {
+        let data_root: Option<String> = self.opt_from_str("pipeline::data_root")?;
+        let cache_dir: Option<String> = self.opt_from_str("pipeline::cache_dir")?;
+        Ok(
+            ConfigLayer {
+                data_root:      data_root.map(PathBuf::from),
+                fred_api_key:   self.opt_from_str("pipeline::fred_api_key")?,
+                bind:           self.opt_from_str("pipeline::bind")?,
+                port:           self.opt_from_str("pipeline::port")?,
+                cache_dir:      cache_dir.map(PathBuf::from),
+                cache_ttl:      self.opt_from_str("pipeline::cache_ttl")?,
+                workers:        self.opt_from_str("pipeline::workers")?,
+            }
+        )
+    }
+}
+
+// === PipelineConfig =============================================================================
+
+/// The settings every stage of the pipeline runs with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipelineConfig {
+
+    /// An absolute path.
+    pub data_root:      PathBuf,
+
+    /// Empty if no key is configured.
+    pub fred_api_key:   String,
+    pub bind:           String,
+    pub port:           u16,
+    pub cache_dir:      PathBuf,
+    pub cache_ttl:      Duration,
+
+    /// The number of buckets of series worked on at once.
+    pub workers:        usize,
+}
+
+impl PipelineConfig {
+
+    /// Load the config file, if one is given or named by `GP_CONFIG`, override it with the
+    /// environment and then with `args`, and check the result.
+    pub fn load(file: Option<&Path>, args: ConfigLayer) -> Result<Self> {
+        PipelineConfig::load_with(file, args, |key| env::var(key).ok())
+    }
+
+    /// As [`load`](#method.load), looking up environment variables with `var`.
+    pub fn load_with<F>(file: Option<&Path>, args: ConfigLayer, var: F) -> Result<Self>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let file = file.map(Path::to_path_buf).or_else(|| var(CONFIG_ENV).map(PathBuf::from));
+        let layer = match file {
+            Some(file) => ConfigLayer::from_file(file)?,
+            None => ConfigLayer::default(),
+        };
+        PipelineConfig::resolve(layer.merge(ConfigLayer::from_env(var)?).merge(args))
+    }
+
+    /// Fill in the defaults of unset settings, and check every setting.
+    pub fn resolve(layer: ConfigLayer) -> Result<Self> {
+        let data_root = match layer.data_root {
+            Some(path) => path,
+            None => {
+                let message = "No data root is given as an argument, as pipeline::data_root or in \
+                    GP_DATA_ROOT";
+                return Err(Error::Config(message.to_string()))
+            },
+        };
+        let data_root = match data_root.canonicalize() {
+            Ok(path) if path.is_dir() => path,
+            _ => return Err(Error::DirectoryNotFound(data_root)),
+        };
+
+        let fred_api_key = layer.fred_api_key.unwrap_or_default();
+        let is_key = fred_api_key.len() == 32 &&
+            fred_api_key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+        if !fred_api_key.is_empty() && !is_key {
+            let message = "fred_api_key is not 32 lowercase letters and digits";
+            return Err(Error::Config(message.to_string()))
+        }
+
+        let bind = layer.bind.unwrap_or_else(|| DEFAULT_BIND.to_string());
+        if bind.parse::<IpAddr>().is_err() {
+            return Err(Error::Config(format!("bind [{}] is not an IP address", bind)))
+        }
+
+        let port = layer.port.unwrap_or(DEFAULT_PORT);
+        if port == 0 {
+            return Err(Error::Config("port must not be 0".to_string()))
+        }
+
+        let workers = layer.workers.unwrap_or(DEFAULT_WORKERS);
+        if workers == 0 {
+            return Err(Error::Config("workers must be at least 1".to_string()))
+        }
+
+        let cache_dir = layer.cache_dir.unwrap_or_else(|| PathBuf::from(CACHE_DIR));
+
+        Ok(
+            PipelineConfig {
+                cache_dir:  data_root.join(cache_dir),
+                cache_ttl:  layer.cache_ttl.map(Duration::from_secs).unwrap_or(DEFAULT_TTL),
+                data_root,
+                fred_api_key,
+                bind,
+                port,
+                workers,
+            }
+        )
+    }
+
+    /// The settings of a FRED client using the configured key and cache.
+    pub fn fred_config(&self, offline: bool) -> FredConfig {
+        FredConfig {
+            api_key:    self.fred_api_key.clone(),
+            cache:      Some(ResponseCache::new(&self.cache_dir, self.cache_ttl, offline)),
+            ..FredConfig::default()
+        }
+    }
+
+    /// The settings of a server listening on the configured address.
+    pub fn server_config(&self, series_spec: &str, watch: bool) -> ServerConfig {
+        ServerConfig {
+            data_root:  self.data_root.clone(),
+            bind:       self.bind.clone(),
+            port:       self.port,
+            series_spec: series_spec.to_string(),
+            watch,
+        }
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::fs;
+
+    fn no_env(_key: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn sources_should_override_in_order() {
+        let dir = std::env::temp_dir().join("graphics_pipeline_config");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("data")).unwrap();
+
+        fs::write(
+            dir.join("pipeline.keytree"),
+            "pipeline:\n    data_root:  data\n    port:       9000\n    workers:    2\n    include:    secrets.keytree\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("secrets.keytree"),
+            "pipeline:\n    fred_api_key:   abcdefghijklmnopqrstuvwxyz012345\n",
+        )
+        .unwrap();
+
+        let env = |key: &str| match key {
+            "GP_WORKERS" => Some("8".to_string()),
+            "GP_CACHE_DIR" => Some("fred_cache".to_string()),
+            _ => None,
+        };
+        let file = dir.join("pipeline.keytree");
+        let args = ConfigLayer { port: Some(9001), ..ConfigLayer::default() };
+        let config = PipelineConfig::load_with(Some(file.as_path()), args, env).unwrap();
+
+        let data_root = dir.join("data").canonicalize().unwrap();
+        assert_eq!(config.data_root, data_root);
+        assert_eq!(config.fred_api_key, "abcdefghijklmnopqrstuvwxyz012345");
+        assert_eq!(config.bind, DEFAULT_BIND);
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.workers, 8);
+        assert_eq!(config.cache_dir, data_root.join("fred_cache"));
+        assert_eq!(config.cache_ttl, DEFAULT_TTL);
+
+        fs::write(&file, "pipeline:\n    port:   http\n").unwrap();
+        assert!(matches!(
+            PipelineConfig::load_with(Some(file.as_path()), ConfigLayer::default(), no_env),
+            Err(Error::Spec(_)),
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_settings_should_fail_at_startup() {
+        let root = std::env::temp_dir();
+        let layer = |env: &[(&str, &str)]| -> Result<PipelineConfig> {
+            let mut layer = ConfigLayer { data_root: Some(root.clone()), ..ConfigLayer::default() };
+            for (key, value) in env {
+                let var = |k: &str| if k == *key { Some(value.to_string()) } else { None };
+                layer = layer.merge(ConfigLayer::from_env(var)?);
+            }
+            PipelineConfig::resolve(layer)
+        };
+
+        assert!(layer(&[]).is_ok());
+        assert!(matches!(layer(&[("GP_WORKERS", "0")]), Err(Error::Config(_))));
+        assert!(matches!(layer(&[("GP_PORT", "http")]), Err(Error::Config(_))));
+        assert!(matches!(layer(&[("GP_BIND", "localhost")]), Err(Error::Config(_))));
+        assert!(matches!(layer(&[("FRED_API_KEY", "short")]), Err(Error::Config(_))));
+        assert!(matches!(
+            layer(&[("GP_DATA_ROOT", "/graphics_pipeline/missing")]),
+            Err(Error::DirectoryNotFound(_)),
+        ));
+        assert!(matches!(
+            PipelineConfig::resolve(ConfigLayer::default()),
+            Err(Error::Config(_)),
+        ));
+    }
+}
@@ -9,12 +9,20 @@
 //!
 //! We'll start by just copying the data from /raw_data/ directly using series_spec.
 
-// load series_spec
-
-// loop through all the series and copy.
-
 use crate::{
+    error::{Error, Result},
+    file_resources::impls::CsvRawData,
+    file_resources::IntoResources,
+    primitives::SeriesId,
     series_spec::SeriesSpec,
+    series_to_disk::{series_dir, spec_map_from_spec},
+    ts_graphics::series_data::SeriesData,
+    workers::{map_series, Batch},
+};
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
 };
 use time_series::{
     Date,
@@ -22,18 +30,34 @@ use time_series::{
     Value,
 };
 
-// pub fn save_transforms<P, S>(root_dir: P, ts_spec_path: S) -> Result<()>
-// where
-//     P: AsRef<Path>,
-//     S: AsRef<OsStr>,
-// {
-//     let root: PathBuf = root_dir.as_ref().to_path_buf();
-//     let path: &OsStr = ts_spec_path.as_ref();
-//     let spec_map: SeriesSpecMap = spec_map_from_spec(root, path)?;
-//     
-//     Ok(())
-// 
-// }
+/// Copy the raw data of each series in the spec to
+/// `transformed_data/<data_type>/<country>/<series_id>.csv`, up to `workers` buckets at once,
+/// returning the file written for each series. Data which does not parse is not copied.
+pub fn save_transforms<P, S>(
+    root_dir: P,
+    series_spec_path: S,
+    workers: usize) -> Result<Batch<SeriesId, PathBuf>>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let root: PathBuf = root_dir.as_ref().to_path_buf();
+    let spec_map = spec_map_from_spec(&root, series_spec_path)?;
+    Ok(map_series(&spec_map, workers, |series_spec| {
+        let csv = PathBuf::from(series_spec.series_id().to_string()).with_extension("csv");
+        let raw = CsvRawData {
+            country:    series_spec.country(),
+            data_type:  series_spec.data_type(),
+        };
+        let s = raw.from_file(&root, &csv)?;
+        SeriesData::from_csv(&s)
+            .map_err(|e| Error::Transform(format!("{}: {}", csv.display(), e)))?;
+
+        let path = series_dir(&root, "transformed_data", series_spec)?.join(&csv);
+        fs::write(&path, s)?;
+        Ok(path)
+    }))
+}
 
 #[cfg(test)]
 pub mod tests {
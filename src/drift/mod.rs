@@ -0,0 +1,244 @@
+//! Find drift between the specifications and the data on disk.
+//!
+//! `verify_raw` checks that every series in `series_spec.keytree` has a CSV file. This module
+//! checks the other direction: files in `raw_data/` and `transformed_data/` that are no longer in
+//! the series specification, `.meta` files without a `.csv` file, and series that a `TSSpec`
+//! graphic refers to but that are missing from the series specification.
+
+use anyhow::anyhow;
+use crate::{
+    countries::Country,
+    error::Result,
+    file_resources::{data_buckets, from_path_arg, IntoResources, Resources},
+    file_resources::impls::{CsvRawData, CsvTransformedData},
+    primitives::{DataType, SeriesId},
+    series_to_disk::{spec_map_from_spec, SeriesSpecMap},
+    ts_graphics::ts_spec::{ts_spec_from_file, TSSpec},
+};
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Checks for drift between the specifications and the data on disk, displays results and returns
+/// the drift found. If `prune` is true, orphaned files are deleted.
+pub fn verify_drift<P, S>(
+    data_root: P,
+    series_spec_file: S,
+    ts_spec_file: Option<&str>,
+    prune: bool) -> Result<Vec<Drift>>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let root: PathBuf = from_path_arg(data_root);
+    let spec_map = spec_map_from_spec(&root, series_spec_file)?;
+
+    let mut drifts = find_file_drift(&root, &spec_map)?;
+    if let Some(file) = ts_spec_file {
+        let ts_spec = ts_spec_from_file(&root, file)?;
+        drifts.extend(find_ts_spec_drift(&ts_spec, &spec_map));
+    }
+
+    for drift in &drifts {
+        println!("{}", drift);
+    }
+    if drifts.is_empty() {
+        println!(" ok  no drift");
+    }
+
+    if prune {
+        for path in prune_files(&drifts)? {
+            println!("pruned {}", path.display());
+        }
+    }
+    Ok(drifts)
+}
+
+// === Drift ======================================================================================
+
+/// A difference between the specifications and the data on disk.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Drift {
+
+    /// A CSV file whose series is not in the series specification.
+    OrphanCsv(PathBuf),
+
+    /// A `.meta` file whose series is not in the series specification.
+    OrphanMeta(PathBuf),
+
+    /// A `.meta` file without a matching `.csv` file in the same directory.
+    MetaWithoutCsv(PathBuf),
+
+    /// A series referred to by a graphic in a `TSSpec` page which is not in the series
+    /// specification.
+    NotInSeriesSpec {
+        data_type:  DataType,
+        country:    Country,
+        index:      usize,
+        series_id:  SeriesId,
+    },
+}
+
+impl Drift {
+
+    /// The file that can be deleted to resolve the drift, if there is one.
+    pub fn prunable(&self) -> Option<&Path> {
+        match self {
+            Drift::OrphanCsv(path)      => Some(path),
+            Drift::OrphanMeta(path)     => Some(path),
+            Drift::MetaWithoutCsv(path) => Some(path),
+            Drift::NotInSeriesSpec {..} => None,
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::OrphanCsv(path)      => write!(f, "orphan csv   {}", path.display()),
+            Drift::OrphanMeta(path)     => write!(f, "orphan meta  {}", path.display()),
+            Drift::MetaWithoutCsv(path) => write!(f, "meta no csv  {}", path.display()),
+            Drift::NotInSeriesSpec { data_type, country, index, series_id } => {
+                write!(f, "not in spec  {} ({} {} page {})", series_id, country, data_type, index)
+            },
+        }
+    }
+}
+
+// === Detection ==================================================================================
+
+/// Return all CSV and `.meta` files in `raw_data/` and `transformed_data/` that are not in the
+/// series specification, and all `.meta` files without a `.csv` file.
+pub fn find_file_drift<P: AsRef<Path>>(data_root: P, spec_map: &SeriesSpecMap) -> Result<Vec<Drift>> {
+    let root: PathBuf = from_path_arg(data_root);
+    let mut acc = Vec::new();
+
+    if root.join("raw_data").is_dir() {
+        for (data_type, country) in data_buckets(&root, "raw_data")? {
+            let all = CsvRawData { country, data_type }.all_files_in_dir(&root)?;
+            acc.extend(bucket_drift(&all, spec_map)?);
+        }
+    }
+
+    if root.join("transformed_data").is_dir() {
+        for (data_type, country) in data_buckets(&root, "transformed_data")? {
+            let all = CsvTransformedData { country, data_type }.all_files_in_dir(&root)?;
+            acc.extend(bucket_drift(&all, spec_map)?);
+        }
+    }
+    Ok(acc)
+}
+
+/// Return every series referred to by a graphic in the `TSSpec` which is not in the series
+/// specification.
+pub fn find_ts_spec_drift(ts_spec: &TSSpec, spec_map: &SeriesSpecMap) -> Vec<Drift> {
+    let mut acc = Vec::new();
+    for page in ts_spec.pages.iter() {
+        for graphic in page.graphics.iter() {
+            for series_id in graphic.series_ids.iter() {
+                if !spec_map.contains(&series_id.stem()) {
+                    acc.push(
+                        Drift::NotInSeriesSpec {
+                            data_type:  page.data_type,
+                            country:    page.country,
+                            index:      page.index,
+                            series_id:  series_id.clone(),
+                        }
+                    );
+                }
+            }
+        }
+    }
+    acc.dedup();
+    acc
+}
+
+/// Delete every file that can be pruned, returning the deleted paths.
+pub fn prune_files(drifts: &[Drift]) -> Result<Vec<PathBuf>> {
+    let mut acc = Vec::new();
+    for path in drifts.iter().filter_map(|drift| drift.prunable()) {
+        fs::remove_file(path)
+            .map_err(|_| anyhow!("Failed to remove '{}'", path.display()))?;
+        acc.push(path.to_path_buf());
+    }
+    Ok(acc)
+}
+
+// Drift within the files of a single `(DataType, Country)` directory.
+fn bucket_drift(all: &Resources, spec_map: &SeriesSpecMap) -> Result<Vec<Drift>> {
+    let mut acc = Vec::new();
+    let csvs = all.filter_by_ext(vec!["csv"]);
+
+    for path in csvs.iter() {
+        if !in_spec(&path, spec_map) {
+            acc.push(Drift::OrphanCsv(path));
+        }
+    }
+
+    for path in all.filter_by_ext(vec!["meta"]).iter() {
+        if !in_spec(&path, spec_map) {
+            acc.push(Drift::OrphanMeta(path));
+        } else if !csvs.iter().any(|csv| csv == path.with_extension("csv")) {
+            acc.push(Drift::MetaWithoutCsv(path));
+        }
+    }
+    Ok(acc)
+}
+
+// Returns true if the file stem, without transformation modifications, is in the specification.
+fn in_spec(path: &Path, spec_map: &SeriesSpecMap) -> bool {
+    match path.file_stem().and_then(|os_str| os_str.to_str()) {
+        Some(stem) => spec_map.contains(&SeriesId::new(stem).stem()),
+        None => false,
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::series_spec::SeriesSpec;
+
+    fn spec_map() -> SeriesSpecMap {
+        vec!(SeriesSpec::new(DataType::U, Country::Australia, SeriesId::new("AUSURAMS")))
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn transformed_series_should_be_in_spec() {
+        assert!(in_spec(Path::new("transformed_data/u/australia/AUSURAMS_a.csv"), &spec_map()));
+        assert!(!in_spec(Path::new("raw_data/u/australia/AUSURANAA.csv"), &spec_map()));
+    }
+
+    #[test]
+    fn bucket_drift_should_find_orphans_and_meta_without_csv() {
+        let all: Resources = vec!(
+            PathBuf::from("raw_data/u/australia/AUSURAMS.meta"),
+            PathBuf::from("raw_data/u/australia/AUSURANAA.csv"),
+        ).into_iter().collect();
+
+        assert_eq!(
+            bucket_drift(&all, &spec_map()).unwrap(),
+            vec!(
+                Drift::OrphanCsv(PathBuf::from("raw_data/u/australia/AUSURANAA.csv")),
+                Drift::MetaWithoutCsv(PathBuf::from("raw_data/u/australia/AUSURAMS.meta")),
+            ),
+        );
+    }
+
+    #[test]
+    fn not_in_series_spec_should_not_be_prunable() {
+        let drift = Drift::NotInSeriesSpec {
+            data_type:  DataType::U,
+            country:    Country::Australia,
+            index:      0,
+            series_id:  SeriesId::new("AUSURANAA"),
+        };
+        assert!(drift.prunable().is_none());
+    }
+}
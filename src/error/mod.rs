@@ -0,0 +1,177 @@
+//! The errors of the library. Functions which find files, load specs or fetch data return
+//! [`Result`](type.Result.html), so that callers can match on what went wrong, and the server can
+//! answer with a status code. Only the `FromStr` implementations, which key trees parse values
+//! with, and the `os_setup` shell helpers use `anyhow`. Their errors convert into `Error::Other`,
+//! unless they wrap an `Error`.
+//! ```
+//! # use graphics_pipeline::error::Error;
+//! # use graphics_pipeline::file_resources::join_paths;
+//! match join_paths("../missing", vec!("specs")) {
+//!     Err(Error::DirectoryNotFound(path)) => assert!(path.ends_with("specs")),
+//!     _ => panic!(),
+//! }
+//! ```
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use crate::{
+    spec_files::diagnostics::SpecErrors,
+    ts_graphics::template::TemplateError,
+};
+use key_tree::KeyTreeError;
+use std::{fmt, io, path::PathBuf};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+
+    /// A file is not among the resources of its directory.
+    FileNotFound { file: PathBuf, dir: PathBuf },
+
+    /// A directory under the data root does not exist.
+    DirectoryNotFound(PathBuf),
+
+    /// A directory holds a file which is not of the type of its resources.
+    UnexpectedFileType { path: PathBuf, expected: String },
+
+    /// Every mistake found in a spec.
+    Spec(SpecErrors),
+
+    /// A request to a data source such as FRED failed.
+    DataSource(String),
+
+    /// Data could not be transformed.
+    Transform(String),
+
+    /// A setting of the pipeline is missing or invalid.
+    Config(String),
+
+    /// A template failed to parse or render.
+    Template(TemplateError),
+
+    Io(io::Error),
+
+    Other(anyhow::Error),
+}
+
+impl Error {
+
+    /// The status of a response to a request which failed with this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::FileNotFound { .. } | Error::DirectoryNotFound(_) => StatusCode::NOT_FOUND,
+            Error::DataSource(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FileNotFound { file, dir } => {
+                write!(f, "File '{}' not found in '{}'", file.display(), dir.display())
+            },
+            Error::DirectoryNotFound(dir) => write!(f, "Directory '{}' not found", dir.display()),
+            Error::UnexpectedFileType { path, expected } => {
+                write!(f, "{} is not of type {}", path.display(), expected)
+            },
+            Error::Spec(errors) => write!(f, "{}", errors),
+            Error::DataSource(message) => write!(f, "Data source failed: {}", message),
+            Error::Transform(message) => write!(f, "Transform failed: {}", message),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+            Error::Template(e) => write!(f, "{}", e),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spec(errors) => Some(errors),
+            Error::Template(e) => Some(e),
+            Error::Io(e) => Some(e),
+            Error::Other(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<SpecErrors> for Error {
+    fn from(errors: SpecErrors) -> Self {
+        Error::Spec(errors)
+    }
+}
+
+impl From<TemplateError> for Error {
+    fn from(e: TemplateError) -> Self {
+        Error::Template(e)
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Self {
+        Error::Other(e.into())
+    }
+}
+
+impl From<KeyTreeError> for Error {
+    fn from(e: KeyTreeError) -> Self {
+        Error::Other(e.into())
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        match e.downcast::<SpecErrors>() {
+            Ok(errors) => Error::Spec(errors),
+            Err(e) => Error::Other(e),
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        self.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status()).body(self.to_string())
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn anyhow_should_keep_the_variant() {
+        let e: anyhow::Error = Error::DirectoryNotFound(PathBuf::from("specs")).into();
+        assert!(matches!(Error::from(e), Error::DirectoryNotFound(path) if path == Path::new("specs")));
+
+        let e = anyhow::anyhow!("Something else");
+        assert!(matches!(Error::from(e), Error::Other(_)));
+    }
+
+    #[test]
+    fn missing_files_should_be_not_found() {
+        let e = Error::FileNotFound { file: PathBuf::from("a.csv"), dir: PathBuf::from("raw_data") };
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+        assert_eq!(e.to_string(), "File 'a.csv' not found in 'raw_data'");
+    }
+}
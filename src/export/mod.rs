@@ -0,0 +1,423 @@
+//! Export every time-series page as a static site, which can be served by any static host.
+//!
+//! The layout mirrors the routes of the `shared_http` server:
+//! ```text
+//! out/
+//!     index.html                          links to each data type
+//!     favicon.png
+//!     css/style.css
+//!     js/*.js
+//!     pid/css/style.css
+//!     pid/js/*.js
+//!     data/u/australia/AUSURAMS.json
+//!     u/index.html                        links to each country
+//!     u/australia/index.html              links to each page
+//!     u/australia/0.html
+//!     u/australia/0.png                   Open Graph image of the first graphic
+//! ```
+
+use anyhow::anyhow;
+use crate::{
+    countries::Country,
+    error::Result,
+    file_resources::{file_name, from_path_arg, IntoResources},
+    file_resources::impls::{PidGraphicCss, PidGraphicsFavIcon, PidGraphicsJs, TSCss, TSGraphicsJs},
+    primitives::DataType,
+    ts_graphics::html::{graphic_svg, page_image_path, page_path, render_page, series_data_path},
+    ts_graphics::html::{RenderOptions, SeriesDataMode},
+    ts_graphics::png::{svg_to_png, PngOptions},
+    ts_graphics::series_data::{load_series, SeriesKey},
+    ts_graphics::svg::SvgOptions,
+    ts_graphics::template::{Context, Templates, Value},
+    ts_graphics::ts_spec::{ts_spec_from_resources, validate_page, PageSpec},
+    workers::{map_bounded, Batch},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The template in `ts_graphics/templates` used for index pages, if there is one.
+pub const INDEX_TEMPLATE: &str = "index.html";
+
+/// Used for index pages when `ts_graphics/templates` has no `index.html`.
+pub const DEFAULT_INDEX_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{ title }}</title>
+<link rel="stylesheet" href="{{ root }}css/style.css">
+<link rel="icon" href="{{ root }}favicon.png">
+</head>
+<body>
+<h1>{{ title }}</h1>
+<ul>
+{% for link in links %}<li><a href="{{ link.href }}">{{ link.text }}</a></li>
+{% endfor %}</ul>
+</body>
+</html>
+"#;
+
+/// The number of files of each kind written by an export.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ExportSummary {
+    pub pages:      usize,
+    pub series:     usize,
+    pub indexes:    usize,
+    pub images:     usize,
+    pub assets:     usize,
+
+    /// The pages which could not be exported, as `u/australia/0.html: error`.
+    pub failed:     Vec<String>,
+}
+
+/// Render every `PageSpec` in every `ts_graphics/spec` file into `out_dir`, with its scripts,
+/// styles, series data and index pages. The pages of up to `workers` countries are rendered at
+/// once, and a page which fails, or breaks the rules of `TSSpec::validate`, is listed in the
+/// summary rather than stopping the export.
+pub fn export_site<P, Q>(data_root: P, out_dir: Q, workers: usize) -> Result<ExportSummary>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let root: PathBuf = from_path_arg(data_root);
+    let out: PathBuf = from_path_arg(out_dir);
+    let mut summary = ExportSummary::default();
+
+    let templates = Templates::new(&root)?;
+    let ts_spec = ts_spec_from_resources(&root)?;
+
+    // Pages which break the rules of the page spec are not exported.
+    let mut invalid: BTreeMap<String, String> = BTreeMap::new();
+    for (i, page) in ts_spec.pages.iter().enumerate() {
+        let violations = validate_page(page, &format!("ts_spec::page[{}]", i));
+        if !violations.is_empty() {
+            let lines: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+            invalid.insert(page_key(page), lines.join("; "));
+        }
+    }
+
+    // Pages share series, so each series is written once before the pages which link it.
+    let mut series_buckets: BTreeMap<(DataType, Country), BTreeSet<SeriesKey>> = BTreeMap::new();
+    let valid = ts_spec.pages.iter().filter(|page| !invalid.contains_key(&page_key(page)));
+    for key in valid.flat_map(PageSpec::series_keys) {
+        series_buckets.entry((key.0, key.1)).or_default().insert(key);
+    }
+    let series_batch: Batch<SeriesKey, ()> = map_bounded(
+        series_buckets.into_values().collect(),
+        workers,
+        |keys| {
+            keys
+                .into_iter()
+                .map(|key| {
+                    let result = export_series(&root, &out, &key);
+                    (key, result)
+                })
+                .collect::<Vec<_>>()
+        },
+    )
+    .into_iter()
+    .flatten()
+    .collect();
+    summary.series = series_batch.done.len();
+    let failed_series: BTreeMap<SeriesKey, String> = series_batch.failed
+        .into_iter()
+        .map(|(key, e)| (key, e.to_string()))
+        .collect();
+
+    let mut buckets: BTreeMap<(DataType, Country), Vec<&PageSpec>> = BTreeMap::new();
+    for page in ts_spec.pages.iter() {
+        buckets.entry((page.data_type, page.country)).or_default().push(page);
+    }
+
+    // Pages are two directories below the site root.
+    let options = RenderOptions {
+        svg_root: Some(root.clone()),
+        ..RenderOptions::new(&root, "../../", SeriesDataMode::Linked)?
+    };
+
+    let batch: Batch<String, bool> = map_bounded(
+        buckets.into_values().collect(),
+        workers,
+        |pages| {
+            let mut options = options.clone();
+            pages
+                .iter()
+                .map(|page| {
+                    let failed = page.series_keys()
+                        .into_iter()
+                        .find_map(|key| failed_series.get(&key));
+                    let result: Result<bool> = match (invalid.get(&page_key(page)), failed) {
+                        (Some(e), _) => Err(anyhow!("Invalid page spec: {}", e).into()),
+                        (None, Some(e)) => Err(anyhow!("Failed to export series: {}", e).into()),
+                        (None, None) => export_page(&root, &out, page, &templates, &mut options),
+                    };
+                    (page_key(page), result)
+                })
+                .collect::<Vec<_>>()
+        },
+    )
+    .into_iter()
+    .flatten()
+    .collect();
+
+    for image in batch.values() {
+        summary.pages += 1;
+        if *image { summary.images += 1 }
+    }
+    summary.failed = batch.failures();
+
+    // Pages which failed are left out of the indexes, so that the site has no broken links.
+    let failed: BTreeSet<&str> = batch.failed.iter().map(|(key, _)| key.as_str()).collect();
+    let pages: Vec<&PageSpec> = ts_spec.pages
+        .iter()
+        .filter(|page| !failed.contains(page_key(page).as_str()))
+        .collect();
+    summary.indexes += export_indexes(&out, &templates, &pages)?;
+    summary.assets += export_assets(&root, &out)?;
+
+    Ok(summary)
+}
+
+// The path of a page, which names it in failures.
+fn page_key(page: &PageSpec) -> String {
+    page_path(page.data_type, page.country, page.index)
+}
+
+/// Render one page into `out_dir` with its Open Graph image, returning whether there was an image.
+/// The page links the series of `PageSpec::series_keys`, which are written by `export_series`.
+/// `options` must link pages two directories below the site root.
+pub fn export_page(
+    data_root: &Path,
+    out_dir: &Path,
+    page: &PageSpec,
+    templates: &Templates,
+    options: &mut RenderOptions) -> Result<bool>
+{
+    options.og_image = export_image(data_root, out_dir, page)?;
+
+    let html = render_page(page, templates, options)?;
+    write(&out_dir.join(page_path(page.data_type, page.country, page.index)), html.as_bytes())?;
+
+    Ok(options.og_image)
+}
+
+/// Copy the scripts, styles and icon of the site into `out_dir`, returning the number of files.
+pub fn export_assets(data_root: &Path, out_dir: &Path) -> Result<usize> {
+    let mut count = 0;
+    count += copy_resources(&TSGraphicsJs, data_root, &out_dir.join("js"))?;
+    count += copy_resources(&TSCss, data_root, &out_dir.join("css"))?;
+    count += copy_resources(&PidGraphicsJs, data_root, &out_dir.join("pid").join("js"))?;
+    count += copy_resources(&PidGraphicCss, data_root, &out_dir.join("pid").join("css"))?;
+    count += copy_resources(&PidGraphicsFavIcon, data_root, out_dir)?;
+    Ok(count)
+}
+
+/// Write the JSON data of a series into `out_dir`.
+pub fn export_series(data_root: &Path, out_dir: &Path, key: &SeriesKey) -> Result<()> {
+    let (data_type, country, series_id) = key;
+    let series_data = load_series(data_root, key)?
+        .ok_or(anyhow!("No data for series [{}]", series_id))?;
+
+    let path = out_dir.join(series_data_path(*data_type, *country, series_id));
+    write(&path, series_data.to_json()?.as_bytes())
+}
+
+// Write the first graphic of a page as its Open Graph image, returning whether there was a graphic
+// with data to draw.
+fn export_image(root: &Path, out: &Path, page: &PageSpec) -> Result<bool> {
+    let graphic = match page.graphics.first() {
+        Some(graphic) => graphic,
+        None => return Ok(false),
+    };
+    let png_options = PngOptions::open_graph();
+    let svg_options = SvgOptions {
+        id:     "og-image".to_string(),
+        width:  png_options.width,
+        height: png_options.height,
+        ..SvgOptions::default()
+    };
+    match graphic_svg(root, page, graphic, &svg_options)? {
+        Some(svg) => {
+            let path = out.join(page_image_path(page.data_type, page.country, page.index));
+            write(&path, &svg_to_png(&svg, &png_options)?)?;
+            Ok(true)
+        },
+        None => Ok(false),
+    }
+}
+
+/// Write an index for the site, for each data type and for each country, returning the number of
+/// index pages written.
+pub fn export_indexes(out: &Path, templates: &Templates, pages: &[&PageSpec]) -> Result<usize> {
+    let index_templates;
+    let (templates, name) = match templates.contains(INDEX_TEMPLATE) {
+        true => (templates, INDEX_TEMPLATE),
+        false => {
+            index_templates = Templates::from_sources(vec!((INDEX_TEMPLATE, DEFAULT_INDEX_TEMPLATE)))?;
+            (&index_templates, INDEX_TEMPLATE)
+        },
+    };
+
+    let mut tree: BTreeMap<DataType, BTreeMap<Country, Vec<&PageSpec>>> = BTreeMap::new();
+    for page in pages.iter().copied() {
+        tree.entry(page.data_type)
+            .or_default()
+            .entry(page.country)
+            .or_default()
+            .push(page);
+    }
+
+    let mut count = 0;
+
+    let links = tree
+        .keys()
+        .map(|data_type| (format!("{}/index.html", data_type), data_type.title().to_string()))
+        .collect();
+    let html = templates.render(name, &index_context("Time-series", "", links))?;
+    write(&out.join("index.html"), html.as_bytes())?;
+    count += 1;
+
+    for (data_type, countries) in tree.iter() {
+        let links = countries
+            .keys()
+            .map(|country| (format!("{}/index.html", country.as_filepath()), country.to_string()))
+            .collect();
+        let html = templates.render(name, &index_context(data_type.title(), "../", links))?;
+        write(&out.join(data_type.to_string()).join("index.html"), html.as_bytes())?;
+        count += 1;
+
+        for (country, pages) in countries.iter() {
+            let mut pages = pages.clone();
+            pages.sort_by_key(|page| page.index);
+
+            let links = pages
+                .iter()
+                .map(|page| {
+                    let series: Vec<String> = page.seriess
+                        .iter()
+                        .map(|series| series.series_id.to_string())
+                        .collect();
+                    (format!("{}.html", page.index), series.join(", "))
+                })
+                .collect();
+            let title = format!("{} {}", country, data_type.title());
+            let html = templates.render(name, &index_context(&title, "../../", links))?;
+            let path = out.join(data_type.to_string()).join(country.as_filepath()).join("index.html");
+            write(&path, html.as_bytes())?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// The template variables for an index page. Links are `(href, text)` pairs.
+fn index_context(title: &str, root: &str, links: Vec<(String, String)>) -> Context {
+    let links: Vec<Value> = links
+        .into_iter()
+        .map(|(href, text)| {
+            let mut map = Context::new();
+            map.insert("href".into(), href.into());
+            map.insert("text".into(), text.into());
+            map.into()
+        })
+        .collect();
+
+    let mut context = Context::new();
+    context.insert("title".into(), title.into());
+    context.insert("root".into(), root.into());
+    context.insert("links".into(), links.into());
+    context
+}
+
+// Copy every file of a resource type into a directory, returning the number of files copied. A
+// resource type whose directory does not exist is skipped.
+fn copy_resources<R: IntoResources>(resource: &R, root: &Path, dest: &Path) -> Result<usize> {
+    if resource.dir(root).is_err() {
+        return Ok(0)
+    }
+    let mut count = 0;
+    for path in resource.into_resources(root)?.iter() {
+        if !path.is_file() { continue }
+        let bytes = fs::read(&path)?;
+        write(&dest.join(file_name(&path)?), &bytes)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Write a file, creating its directory if necessary.
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|_| anyhow!("Failed to create directory '{}'", dir.display()))?;
+    }
+    fs::write(path, bytes).map_err(|_| anyhow!("Failed to write '{}'", path.display()).into())
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use key_tree::KeyTree;
+
+    #[test]
+    fn indexes_should_be_written_for_site_data_type_and_country() {
+        let s = r#"
+          page:
+              country:        Australia
+              data_type:      u
+              index:          0
+
+              series:
+                  data_type:  u
+                  series_id:  AUSURAMS
+
+              graphic:
+                  category:   source
+                  series_id:  AUSURAMS
+        "#;
+        let page: PageSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+
+        let out = std::env::temp_dir().join("graphics_pipeline_export");
+        let _ = fs::remove_dir_all(&out);
+        let templates = Templates::from_sources(Vec::new()).unwrap();
+
+        assert_eq!(export_indexes(&out, &templates, &[&page]).unwrap(), 3);
+
+        let country_index = fs::read_to_string(out.join("u/australia/index.html")).unwrap();
+        assert!(country_index.contains("<a href=\"0.html\">AUSURAMS</a>"));
+        assert!(country_index.contains("href=\"../../css/style.css\""));
+        fs::remove_dir_all(&out).unwrap();
+    }
+    #[test]
+    fn a_series_on_a_page_and_its_graphic_should_be_exported_once() {
+        let s = r#"
+          page:
+              country:        Australia
+              data_type:      u
+              index:          0
+
+              series:
+                  data_type:  u
+                  series_id:  AUSURAMS
+
+              graphic:
+                  category:   source
+                  series_id:  AUSURAMS
+
+              graphic:
+                  category:   cleaned
+                  series_id:  AUSURAMS_a
+        "#;
+        let page: PageSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let ids: Vec<String> = page.series_keys()
+            .into_iter()
+            .map(|(_, _, series_id)| series_id.to_string())
+            .collect();
+        assert_eq!(ids, vec!("AUSURAMS", "AUSURAMS_a"));
+    }
+}
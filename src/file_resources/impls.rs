@@ -4,9 +4,9 @@
 // `Resources` type. The interface for a request for files is then the resource type and a path to
 // the contents directory root.
 
-use anyhow::{anyhow, bail, Result};
 use crate::{
     countries::Country,
+    error::{Error, Result},
     primitives::DataType,
     file_resources::from_path_arg,
     file_resources::IntoResources,
@@ -18,6 +18,7 @@ use std::{path::{Path, PathBuf}};
 // === PidGraphicCss ============================================================================
 
 /// All CSS files.
+#[derive(Clone, Copy, Debug)]
 pub struct PidGraphicCss;
 
 impl IntoResources for PidGraphicCss {
@@ -37,7 +38,7 @@ impl IntoResources for PidGraphicCss {
 // === CsvRawData ============================================================================
 
 /// All CSV data files.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct CsvRawData {
     pub country: Country,
     pub data_type: DataType,
@@ -54,7 +55,7 @@ impl IntoResources for CsvRawData {
 
         path
             .canonicalize()
-            .map_err(|_| anyhow!("File '{}' not found", path.display()))
+            .map_err(|_| Error::DirectoryNotFound(path))
     }
 
     /// ```
@@ -81,7 +82,7 @@ impl IntoResources for CsvRawData {
 // === CsvTransformedData ============================================================================
 
 /// All CSV data files.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct CsvTransformedData {
     pub country: Country,
     pub data_type: DataType,
@@ -98,7 +99,7 @@ impl IntoResources for CsvTransformedData {
 
         path
             .canonicalize()
-            .map_err(|_| anyhow!("File '{}' not found", path.display()))
+            .map_err(|_| Error::DirectoryNotFound(path))
     }
 
     /// ```
@@ -125,6 +126,7 @@ impl IntoResources for CsvTransformedData {
 // === MetaData ===================================================================================
 
 /// All metadata files.
+#[derive(Clone, Copy, Debug)]
 pub struct MetaData {
     pub country: Country,
     pub data_type: DataType,
@@ -141,7 +143,7 @@ impl IntoResources for MetaData {
 
         path
             .canonicalize()
-            .map_err(|_| anyhow!("Directory '{}' not found", path.display()))
+            .map_err(|_| Error::DirectoryNotFound(path))
     }
 
     /// A collection of files of one type.
@@ -169,7 +171,7 @@ impl IntoResources for MetaData {
 
 // === Spec =======================================================================================
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Spec;
 
 impl IntoResources for Spec {
@@ -188,13 +190,16 @@ impl IntoResources for Spec {
             let entry = res_entry?;
             let pb = entry.path();
 
+            // Directories hold fragments, which are read through includes.
+            if pb.is_dir() { continue }
+
             // Accept ".spec" files in the directory
             if pb.extension() == Some("keytree".as_ref()) { 
                 acc.push(pb);
                 continue;
             }
         
-            bail!("{} is not of type .keytree", pb.display());
+            return Err(Error::UnexpectedFileType { path: pb, expected: ".keytree".to_string() })
         }
         Ok(acc.into_iter().collect())
     }
@@ -203,6 +208,7 @@ impl IntoResources for Spec {
 // === PidGraphicsFavIcon ========================================================================
 
 /// The favicon file.
+#[derive(Clone, Copy, Debug)]
 pub struct PidGraphicsFavIcon;
 
 impl IntoResources for PidGraphicsFavIcon {
@@ -222,6 +228,7 @@ impl IntoResources for PidGraphicsFavIcon {
 // === PidGraphicsJS ========================================================================
 
 /// All Javascript helper files
+#[derive(Clone, Copy, Debug)]
 pub struct PidGraphicsJs;
 
 impl IntoResources for PidGraphicsJs {
@@ -247,6 +254,7 @@ impl IntoResources for PidGraphicsJs {
 
 // === TSPageSpec ========================================================================
 
+#[derive(Clone, Copy, Debug)]
 pub struct TSPageSpec;
 
 impl IntoResources for TSPageSpec {
@@ -270,12 +278,15 @@ impl IntoResources for TSPageSpec {
             let entry = res_entry?;
             let pb = entry.path();
 
+            // Directories hold fragments, which are read through includes.
+            if pb.is_dir() { continue }
+
             if pb.extension() == Some("keytree".as_ref()) {
                 acc.push(pb);
                 continue;
             }
             // Reject all other file types.
-            bail!("{} is not of type MetaData", pb.display());
+            return Err(Error::UnexpectedFileType { path: pb, expected: ".keytree".to_string() })
         }
         Ok(acc.into_iter().collect())
     }
@@ -284,6 +295,7 @@ impl IntoResources for TSPageSpec {
 // === TSGraphicJs ========================================================================
 
 /// Javascript for plotting time-series graphics.
+#[derive(Clone, Copy, Debug)]
 pub struct TSGraphicsJs;
 
 impl IntoResources for TSGraphicsJs {
@@ -313,7 +325,7 @@ impl IntoResources for TSGraphicsJs {
                 continue;
             }
             // Reject all other file types.
-            bail!("{} is not of type MetaData", pb.display());
+            return Err(Error::UnexpectedFileType { path: pb, expected: ".js".to_string() })
         }
         Ok(acc.into_iter().collect())
     }
@@ -322,6 +334,7 @@ impl IntoResources for TSGraphicsJs {
 // === TSHtmlTemplate =============================================================================
 
 /// HTML templates for making HTML web pages for displaying time-series graphics. 
+#[derive(Clone, Copy, Debug)]
 pub struct TSHtmlTemplate;
 
 impl IntoResources for TSHtmlTemplate {
@@ -351,7 +364,7 @@ impl IntoResources for TSHtmlTemplate {
                 continue;
             }
             // Reject all other file types.
-            bail!("{} is not a template", pb.display());
+            return Err(Error::UnexpectedFileType { path: pb, expected: ".html".to_string() })
         }
         Ok(acc.into_iter().collect())
     }
@@ -360,6 +373,7 @@ impl IntoResources for TSHtmlTemplate {
 // === TSCss =============================================================================
 
 /// Style file for time-series graphics pages.
+#[derive(Clone, Copy, Debug)]
 pub struct TSCss;
 
 impl IntoResources for TSCss {
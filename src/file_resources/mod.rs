@@ -55,7 +55,7 @@
 //! 
 //!         path
 //!             .canonicalize()
-//!             .map_err(|_| anyhow!("File [{}] not found", path.display()))
+//!             .map_err(|_| Error::DirectoryNotFound(path))
 //!     }
 //! 
 //!     fn into_resources<P: AsRef<Path>>(&self, data_root: P) -> Result<Resources> {
@@ -70,7 +70,11 @@
 
 pub mod impls;
 
-use anyhow::{anyhow, bail, Result};
+use crate::{
+    countries::Country,
+    error::{Error, Result},
+    primitives::DataType,
+};
 use std::path::{Path, PathBuf};
 use std::{ffi::OsStr, fs};
 
@@ -89,7 +93,7 @@ pub fn join_paths<P: AsRef<Path>>(root: P, paths: Vec<&str>) -> Result<PathBuf>
     }
     path  
         .canonicalize()
-        .map_err(|_| anyhow!("Directory '{}' not found", path.display()))
+        .map_err(|_| Error::DirectoryNotFound(path))
 }
 
 /// Returns true if the path has the right extension.
@@ -105,6 +109,39 @@ pub fn extension_is(path: &Path, extension: &str) -> bool {
     }
 }
 
+/// Return the last component of a path as a `&str`.
+pub fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(|os_str| os_str.to_str())
+        .ok_or_else(|| Error::UnexpectedFileType {
+            path:       path.to_path_buf(),
+            expected:   "file with a UTF-8 name".to_string(),
+        })
+}
+
+/// Return the `(DataType, Country)` pairs which have a directory under `data_root/<stage>`, where
+/// stage is a directory like `raw_data` or `transformed_data`. For example the directory
+/// `raw_data/u/australia` returns `(DataType::U, Country::Australia)`.
+pub fn data_buckets<P: AsRef<Path>>(data_root: P, stage: &str) -> Result<Vec<(DataType, Country)>> {
+    let dir = join_paths(data_root, vec!(stage))?;
+
+    let mut acc = Vec::new();
+    for res_entry in fs::read_dir(&dir)? {
+        let data_type_dir = res_entry?.path();
+        if !data_type_dir.is_dir() { continue }
+        let data_type: DataType = file_name(&data_type_dir)?.parse()?;
+
+        for res_entry in fs::read_dir(&data_type_dir)? {
+            let country_dir = res_entry?.path();
+            if !country_dir.is_dir() { continue }
+            let country: Country = file_name(&country_dir)?.parse()?;
+            acc.push((data_type, country));
+        }
+    }
+    acc.sort();
+    Ok(acc)
+}
+
 // === ResourceIter ===============================================================================
 
 pub struct ResourcesIter<'a> {
@@ -178,11 +215,7 @@ impl Resources {
                 None => continue,
             };
             if !ext.contains(&ext_str) {
-                bail!(
-                    "Directory '{}' contained a file with extension '{}'",
-                    self.0[0].parent().unwrap().display(),
-                    ext_str
-                )
+                return Err(Error::UnexpectedFileType { path: pb, expected: ext.join(" or ") })
             }
         }
         Ok(())
@@ -232,10 +265,10 @@ pub trait IntoResources {
         match resources.iter().find(|pb| pb.ends_with(f)) {
             Some(found) => {
                 fs::read_to_string(&found)
-                    .map_err(|_| anyhow!( "File '{}' not found in '{}'", &found.to_str().unwrap(), dir.display()))
+                    .map_err(|_| Error::FileNotFound { file: found.clone(), dir })
             },
             None => {
-                Err(anyhow!("File '{}' not found in '{}'", f.to_str().unwrap(), dir.display()))
+                Err(Error::FileNotFound { file: PathBuf::from(f), dir })
             },
         }
     }
@@ -257,7 +290,7 @@ pub trait IntoResources {
         dir
             .join(f)
             .canonicalize()
-            .map_err(|_| anyhow!("File '{}' not found in '{}'", f.to_str().unwrap(), dir.display()))
+            .map_err(|_| Error::FileNotFound { file: PathBuf::from(f), dir: dir.clone() })
     }
 
     /// Verify that a file is in `Resources`.
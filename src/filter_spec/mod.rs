@@ -1,15 +1,29 @@
 //! Deserialize filter specification into [`FilterSpec`](struct.FilterSpec.html).
 
-use anyhow::{anyhow, Result};
 use crate::{
     countries::Country,
+    error::Result,
     file_resources::IntoResources,
     file_resources::impls::Spec,
     primitives::DataType,
+    spec_files::load_spec_files,
+    spec_files::diagnostics::{parses, KeyRule},
 };
 use key_tree::{KeyTree, KeyTreeError};
 use std::{convert::TryInto, ffi::OsStr, path::Path};
 
+/// The keys of a filter specification.
+pub const FILTER_SCHEMA: &[KeyRule] = &[
+    KeyRule::block("selectors", false),
+    KeyRule::block("selectors::series", false),
+    KeyRule::value("selectors::series::country", true, "country", parses::<Country>),
+    KeyRule::value("selectors::series::data_type", true, "data type", parses::<DataType>),
+    KeyRule::value("selectors::series::tag", false, "tag", parses::<String>),
+    KeyRule::value("selectors::series::enumerate", false, "tag", parses::<String>),
+    KeyRule::value("selectors::series::exclude", false, "word", parses::<String>),
+    KeyRule::value("selectors::series::require", false, "word", parses::<String>),
+];
+
 /// Return the data-structures representing a filter specification.
 /// Return the data-structures representing a source specification.
 /// ```
@@ -23,7 +37,11 @@ where
     P: AsRef<Path>,
 {
     let path = Spec.full_path(data_root, file)?;
-    KeyTree::parse(&path)?.try_into().map_err(|_| anyhow!("File {} not found", path.display()))
+    let mut selectors = Vec::new();
+    for (_, fragment) in load_spec_files::<FilterSpec>(&[path], FILTER_SCHEMA)? {
+        selectors.extend(fragment.0);
+    }
+    Ok(FilterSpec(selectors))
 }
 
 // === FilterSpec =================================================================================
@@ -78,9 +96,7 @@ impl TryInto<FilterSpec> for KeyTree {
     type Error = KeyTreeError;
 
     fn try_into(self) -> std::result::Result<FilterSpec, Self::Error> {
-        dbg!();
         let v: Vec<TagSelector> = self.opt_vec_at("selectors::series")?;
-        dbg!();
         Ok(FilterSpec(v))
     }
 }
@@ -160,23 +176,21 @@ impl TryInto<TagSelector> for KeyTree {
 
 #[cfg(test)]
 mod test {
+    use crate::error::Error;
     use std::path::PathBuf;
 
     #[test]
     fn read_spec_should_fail_if_file_missing() {
         if let Err(e) = super::filter_spec_from_file("../../shared_data", "missing") {
-            assert_eq!(
-                e.to_string(),
-                "File 'missing' not found in '/home/eric/currency.engineering/shared_data/specs'",
-            );
+            assert!(matches!(e, Error::FileNotFound { file, .. } if file == PathBuf::from("missing")));
         }
     }
 
     #[test]
     fn read_spec_should_fail_if_contents_dir_missing() {
-        if let Err(e) = super::filter_spec_from_file("../missing", "anything") {
-            assert_eq!(
-                e.to_string(), "Directory '../missing/specs' not found")
+        match super::filter_spec_from_file("../missing", "anything") {
+            Err(Error::DirectoryNotFound(dir)) => assert!(dir.ends_with("specs")),
+            _ => panic!(),
         }
     }
 
@@ -4,19 +4,18 @@
 
 // Note: We need to be careful with isolating responsibilities.
 
-use anyhow::{Result};
 use crate::{
     countries::Country,
-    primitives::SeriesId,
-    filter_spec::FilterSpec, 
+    error::{Error, Result},
+    primitives::{DataType, SeriesId},
+    filter_spec::filter_spec_from_file,
     filter_spec::TagSelector,
+    fred_client::{FredClient, SeriesItem},
     series_spec::{SeriesSpec, SeriessSpec},
-    file_resources::IntoResources,
-    file_resources::impls::Spec,
+    workers::{map_bounded, Batch},
 };
-use fred_api::FredClient;
-use key_tree::KeyTree;
-use std::{ffi::OsStr, path::{Path, PathBuf}};
+use serde::Serialize;
+use std::{ffi::OsStr, path::Path};
 
 /// TODO
 pub fn filter_spec_to_generic_source_spec() -> Result<()> {
@@ -57,10 +56,72 @@ pub fn countries_with_data() -> Vec<Country> {
     )
 }
 
-/// Takes a filter specification and returns a source specification, printing out details about
-/// which series are selected and which are dropped, for example
+/// A series found by a tag selector of a filter specification, and whether the selector keeps it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Selection {
+    pub data_type:  DataType,
+    pub country:    Country,
+    pub series_id:  SeriesId,
+    pub title:      String,
+    pub selected:   bool,
+}
+
+/// Request the series of each tag selector in a filter specification from FRED through `client`,
+/// with up to `workers` requests at once. The selections are in the order of the selectors, and a
+/// selector whose request fails after its retries is collected with its tag.
+pub fn select_series<P, S>(
+    file: S,
+    root_data: P,
+    client: &FredClient,
+    workers: usize) -> Result<Batch<String, Vec<Selection>>>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let filter_spec = filter_spec_from_file(root_data, file)?;
+    let tag_selectors: Vec<&TagSelector> = filter_spec.iter().collect();
+
+    let batch = map_bounded(tag_selectors, workers, |tag_selector| {
+        let tag = tag(tag_selector);
+        let selections = client
+            .tags_series(&tag)
+            .map(|tags_series| {
+                tags_series.seriess
+                    .iter()
+                    .map(|series_item| Selection {
+                        data_type:  tag_selector.data_type,
+                        country:    tag_selector.country,
+                        series_id:  SeriesId::new(&series_item.id),
+                        title:      series_item.title.clone(),
+                        selected:   is_selected(tag_selector, series_item),
+                    })
+                    .collect::<Vec<Selection>>()
+            });
+        (tag, selections)
+    });
+    Ok(batch.into_iter().collect())
+}
+
+/// The series specification of the selected series.
+pub fn series_spec_from_selections(selections: &[Selection]) -> SeriessSpec {
+    let series = selections
+        .iter()
+        .filter(|selection| selection.selected)
+        .map(|selection| {
+            SeriesSpec::new(selection.data_type, selection.country, selection.series_id.clone())
+        })
+        .collect();
+    SeriessSpec { series }
+}
+
+/// Takes a filter specification and returns a source specification, requesting series from FRED
+/// through `client` with up to `workers` requests at once, and printing out details about which
+/// series are selected and which are dropped, for example
 /// ```ignore
-/// let source_spec = source_spec_from_filter_spec("filter_spec.keytree")?;
+/// let config = PipelineConfig::load(None, ConfigLayer::default())?;
+/// let client = FredClient::new(config.fred_config(false));
+/// let series_spec =
+///     series_spec_from_filter_spec("filter_spec.keytree", &config.data_root, &client, config.workers)?;
 /// ```
 /// The printout looks something like
 /// ```text
@@ -68,47 +129,36 @@ pub fn countries_with_data() -> Vec<Country> {
 /// drop: AUSUEMPNA Adjusted Unemployment in Australia (DISCONTINUED)
 /// drop: AUSUR24NAA Adjusted Unemployment Rate for Persons Ages 20 to 24 in Australia (DISCONTINUED)
 ///       AUSURAMS Adjusted Unemployment Rate in Australia (DISCONTINUED)
-///       AUSURAMS Adjusted Unemployment Rate in Australia (DISCONTINUED)
 ///       AUSURANAA Adjusted Unemployment Rate for Adults in Australia (DISCONTINUED)
 /// ```
-pub fn series_spec_from_filter_spec<P, S>(file: S, root_data: P) -> Result<SeriessSpec>
+pub fn series_spec_from_filter_spec<P, S>(
+    file: S,
+    root_data: P,
+    client: &FredClient,
+    workers: usize) -> Result<SeriessSpec>
 where
     P: AsRef<Path>,
     S: AsRef<OsStr>,
 {
-    let f: &OsStr = file.as_ref();
-    let pb: PathBuf = root_data.as_ref().to_path_buf();
-
-    let path = Spec.dir(pb)?.join(f);
-
-    let filter_spec: FilterSpec = KeyTree::parse(path)?.try_into()?;
-
-    let mut acc = Vec::new();
-
-    for tag_selector in filter_spec.iter() {
-
-        println!("");
-        println!("{} {}", tag_selector.country, tag_selector.data_type);
-
-        let tag = tag(&tag_selector);
-
-        let series_items = FredClient::tags_series(&tag)?.seriess;
-
-        for series_item in series_items.iter() {
-
-            let series_id = SeriesId::new(&series_item.id.clone());
-
-            if is_selected(&tag_selector, series_item) {
-                println!("      {} {}", series_item.id, series_item.title);
-                acc.push(SeriesSpec::new(tag_selector.data_type, tag_selector.country, series_id));
-                println!("      {} {}", series_item.id, series_item.title);
-                
-            } else {
-                println!("drop: {} {}", series_item.id, series_item.title);
-            }
+    let batch = select_series(file, root_data, client, workers)?;
+    let selections: Vec<Selection> = batch.values().flatten().cloned().collect();
+
+    let mut bucket = None;
+    for selection in selections.iter() {
+        if bucket != Some((selection.country, selection.data_type)) {
+            println!("");
+            println!("{} {}", selection.country, selection.data_type);
+            bucket = Some((selection.country, selection.data_type));
         }
+        match selection.selected {
+            true => println!("      {} {}", selection.series_id, selection.title),
+            false => println!("drop: {} {}", selection.series_id, selection.title),
+        }
+    }
+    if !batch.is_ok() {
+        return Err(Error::DataSource(batch.failures().join("\n")))
     }
-    Ok(SeriessSpec { series: acc })
+    Ok(series_spec_from_selections(&selections))
 }
 
     // /// Takes a [`FredSeriesFilter`](struct.FredSeriesFilter.html) and returns a [`SeriesSpecMap`](struct.SeriesSpecMap.html).
@@ -209,7 +259,7 @@ pub fn tag(tag_selector: &TagSelector) -> String {
 //     Ok(v)
 // }
 
-fn is_selected(tag_selector: &TagSelector, series_item: &fred_api::SeriesItem) -> bool {
+fn is_selected(tag_selector: &TagSelector, series_item: &SeriesItem) -> bool {
 
     let title = &series_item.title.clone();
 
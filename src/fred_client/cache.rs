@@ -0,0 +1,126 @@
+//! Responses from FRED kept on disk, so that repeated requests are fast and reproducible.
+//!
+//! ```text
+//! <cache_dir>/fred/
+//!     tags_series/<checksum>.json
+//!     series/<checksum>.json
+//!     series_observations/<checksum>.json
+//! ```
+//! A response is named by the checksum of the base URL, its path and parameters, without the API
+//! key, so the same request always finds the same file, and a test server never finds the
+//! responses of FRED. Responses older than the TTL are requested again,
+//! except offline, when any cached response is used and nothing else is requested. The cache
+//! directory is `cache` in the data root unless it is configured.
+
+use crate::{
+    error::Result,
+    manifest::checksum,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The default directory of the cache under the data root.
+pub const CACHE_DIR: &str = "cache";
+
+/// How long a cached response is used before it is requested again.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where responses are cached and for how long.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseCache {
+    pub dir:        PathBuf,
+    pub ttl:        Duration,
+
+    /// Serve only from the cache, whatever the age of a response.
+    pub offline:    bool,
+}
+
+impl ResponseCache {
+
+    /// The cache in `fred` under `cache_dir`, which is `cache` in the data root by default.
+    pub fn new<P: AsRef<Path>>(cache_dir: P, ttl: Duration, offline: bool) -> Self {
+        ResponseCache { dir: cache_dir.as_ref().join("fred"), ttl, offline }
+    }
+
+    /// The file of the response to a request to `base_url`.
+    /// ```
+    /// # use graphics_pipeline::fred_client::cache::{ResponseCache, DEFAULT_TTL};
+    /// # use graphics_pipeline::fred_client::FRED_BASE_URL;
+    /// let cache = ResponseCache::new("data/cache", DEFAULT_TTL, false);
+    /// let params = [("tag_names", "loans;australia")];
+    /// let file = cache.file(FRED_BASE_URL, "tags/series", &params);
+    /// assert!(file.starts_with("data/cache/fred/tags_series"));
+    /// assert_eq!(file, cache.file(FRED_BASE_URL, "tags/series", &params));
+    /// assert_ne!(file, cache.file("http://127.0.0.1:8080", "tags/series", &params));
+    /// ```
+    pub fn file(&self, base_url: &str, path: &str, params: &[(&str, &str)]) -> PathBuf {
+        let mut request = format!("{}/{}", base_url.trim_end_matches('/'), path);
+        for (key, value) in params {
+            request.push_str(&format!("&{}={}", key, value));
+        }
+        self.dir
+            .join(path.replace('/', "_"))
+            .join(checksum(request.as_bytes()))
+            .with_extension("json")
+    }
+
+    /// The cached response in `file`, unless there is none or it has expired.
+    pub fn read(&self, file: &Path) -> Result<Option<String>> {
+        if !file.is_file() {
+            return Ok(None)
+        }
+        let age = SystemTime::now()
+            .duration_since(fs::metadata(file)?.modified()?)
+            .unwrap_or_default();
+        match self.offline || age <= self.ttl {
+            true => Ok(Some(fs::read_to_string(file)?)),
+            false => Ok(None),
+        }
+    }
+
+    /// Cache a response in `file`. The response is written beside the file and moved into place,
+    /// so a worker, or another process, never reads half a response.
+    pub fn write(&self, file: &Path, body: &str) -> Result<()> {
+        if let Some(dir) = file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = file.with_extension(format!(
+            "{}.{:?}.tmp",
+            std::process::id(),
+            std::thread::current().id(),
+        ));
+        fs::write(&tmp, body)?;
+        fs::rename(&tmp, file)?;
+        Ok(())
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::fred_client::FRED_BASE_URL;
+
+    #[test]
+    fn expired_responses_should_only_be_read_offline() {
+        let root = std::env::temp_dir().join("graphics_pipeline_response_cache");
+        let _ = fs::remove_dir_all(&root);
+
+        let cache = ResponseCache::new(root.join(CACHE_DIR), Duration::ZERO, false);
+        let file = cache.file(FRED_BASE_URL, "series", &[("series_id", "AUSURAMS")]);
+        assert_eq!(cache.read(&file).unwrap(), None);
+
+        cache.write(&file, "{}").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(cache.read(&file).unwrap(), None);
+
+        let offline = ResponseCache { offline: true, ..cache };
+        assert_eq!(offline.read(&file).unwrap(), Some("{}".to_string()));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,510 @@
+//! A client for the FRED API which keeps to its request limits.
+//!
+//! Every request waits for a token from a [`TokenBucket`], so a batch running on several workers
+//! stays under the rate FRED allows. A request which fails with a connection error, `429` or a
+//! `5xx` status is retried after an exponential backoff with jitter, or after the `Retry-After`
+//! delay the server gives. Other statuses fail at once. Each attempt is counted against the
+//! request budget of the client, if it has one, so a run against a failing server ends.
+//!
+//! With a [`ResponseCache`](cache/struct.ResponseCache.html), a response is read from disk if it
+//! has been cached and has not expired, and such reads are neither rate limited nor counted.
+
+/// Responses from FRED kept on disk under the data root.
+pub mod cache;
+
+use crate::{
+    error::{Error, Result},
+    fred_client::cache::ResponseCache,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    hash::{BuildHasher, Hasher},
+    sync::{atomic::{AtomicU32, Ordering}, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The root of the FRED API.
+pub const FRED_BASE_URL: &str = "https://api.stlouisfed.org/fred";
+
+/// The environment variable holding the FRED API key.
+pub const API_KEY_ENV: &str = "FRED_API_KEY";
+
+// === FredConfig =================================================================================
+
+/// Where the client sends requests, and how fast and how often.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FredConfig {
+    pub base_url:               String,
+    pub api_key:                String,
+
+    /// The sustained rate of requests. FRED allows 120 a minute.
+    pub requests_per_second:    f64,
+
+    /// The number of requests which may be made at once after a quiet period.
+    pub burst:                  u32,
+
+    /// The number of times a retryable failure is retried.
+    pub max_retries:            u32,
+
+    /// The delay before the first retry, which doubles with each retry.
+    pub base_delay:             Duration,
+    pub max_delay:              Duration,
+
+    /// The most requests the client makes, counting retries. `None` is unlimited.
+    pub budget:                 Option<u32>,
+
+    pub timeout:                Duration,
+
+    /// Where responses are cached. Without it every request goes to FRED.
+    pub cache:                  Option<ResponseCache>,
+}
+
+impl Default for FredConfig {
+    fn default() -> Self {
+        FredConfig {
+            base_url:               FRED_BASE_URL.to_string(),
+            api_key:                env::var(API_KEY_ENV).unwrap_or_default(),
+            requests_per_second:    2.0,
+            burst:                  4,
+            max_retries:            5,
+            base_delay:             Duration::from_millis(500),
+            max_delay:              Duration::from_secs(30),
+            budget:                 None,
+            timeout:                Duration::from_secs(30),
+            cache:                  None,
+        }
+    }
+}
+
+// === TokenBucket ================================================================================
+
+/// A rate limiter which holds up to `burst` tokens and refills at `per_second`.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity:   f64,
+    per_second: f64,
+
+    // The tokens at an instant. Tokens below zero are reserved by callers which are waiting.
+    state:      Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+
+    /// A full bucket. `per_second` must be positive.
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        TokenBucket { capacity, per_second, state: Mutex::new((capacity, Instant::now())) }
+    }
+
+    /// Take a token at `now`, returning how long to wait before it may be used.
+    /// ```
+    /// # use graphics_pipeline::fred_client::TokenBucket;
+    /// # use std::time::{Duration, Instant};
+    /// let bucket = TokenBucket::new(4.0, 2);
+    /// let now = Instant::now();
+    /// assert_eq!(bucket.reserve(now), Duration::ZERO);
+    /// assert_eq!(bucket.reserve(now), Duration::ZERO);
+    /// assert_eq!(bucket.reserve(now), Duration::from_millis(250));
+    /// ```
+    pub fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, last) = *state;
+        let refill = now.saturating_duration_since(last).as_secs_f64() * self.per_second;
+        let tokens = (tokens + refill).min(self.capacity) - 1.0;
+        *state = (tokens, now.max(last));
+
+        match tokens >= 0.0 {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64(-tokens / self.per_second),
+        }
+    }
+
+    /// Block until a token is available and take it.
+    pub fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+// === Backoff ====================================================================================
+
+/// The delay before retry `attempt`, counting from zero. The delay doubles from `base` up to
+/// `max`, and `jitter` in `[0, 1)` spreads it over the upper half so that workers which failed
+/// together do not retry together.
+/// ```
+/// # use graphics_pipeline::fred_client::backoff;
+/// # use std::time::Duration;
+/// let base = Duration::from_millis(100);
+/// let max = Duration::from_secs(1);
+/// assert_eq!(backoff(0, base, max, 0.0), Duration::from_millis(50));
+/// assert_eq!(backoff(2, base, max, 0.5), Duration::from_millis(300));
+/// assert_eq!(backoff(10, base, max, 0.0), Duration::from_millis(500));
+/// ```
+pub fn backoff(attempt: u32, base: Duration, max: Duration, jitter: f64) -> Duration {
+    let delay = base.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+    delay.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+// A number in `[0, 1)` which differs between calls.
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Whether a request which failed with `status` may succeed later.
+fn is_retryable(status: u16) -> bool {
+    status == 429 || (500..600).contains(&status)
+}
+
+// === FredClient =================================================================================
+
+/// A list of series, from `tags/series` or `series`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Seriess {
+    pub seriess: Vec<SeriesItem>,
+}
+
+/// A series as FRED lists it, with its metadata.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SeriesItem {
+    pub id:     String,
+    pub title:  String,
+
+    #[serde(default)]
+    pub frequency:              String,
+    #[serde(default)]
+    pub units:                  String,
+    #[serde(default)]
+    pub seasonal_adjustment:    String,
+    #[serde(default)]
+    pub last_updated:           String,
+    #[serde(default)]
+    pub realtime_start:         String,
+    #[serde(default)]
+    pub observation_start:      String,
+    #[serde(default)]
+    pub observation_end:        String,
+}
+
+/// The observations of a series, from `series/observations`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Observations {
+    pub observations: Vec<Observation>,
+}
+
+impl Observations {
+
+    /// The observations as a CSV file like those downloaded from FRED.
+    /// ```
+    /// # use graphics_pipeline::fred_client::{Observation, Observations};
+    /// let observations = Observations {
+    ///     observations: vec!(Observation { date: "2020-01-01".into(), value: "5.1".into() }),
+    /// };
+    /// assert_eq!(observations.to_csv(), "DATE,VALUE\n2020-01-01,5.1\n");
+    /// ```
+    pub fn to_csv(&self) -> String {
+        let mut s = String::from("DATE,VALUE\n");
+        for observation in self.observations.iter() {
+            s.push_str(&format!("{},{}\n", observation.date, observation.value));
+        }
+        s
+    }
+}
+
+/// A value of a series on a date. Missing values are `.`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Observation {
+    pub date:   String,
+    pub value:  String,
+}
+
+/// A FRED client which may be shared by the workers of a batch.
+#[derive(Debug)]
+pub struct FredClient {
+    config:     FredConfig,
+    agent:      ureq::Agent,
+    limiter:    TokenBucket,
+    requests:   AtomicU32,
+}
+
+impl FredClient {
+
+    pub fn new(config: FredConfig) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(config.timeout).build();
+        let limiter = TokenBucket::new(config.requests_per_second, config.burst);
+        FredClient { config, agent, limiter, requests: AtomicU32::new(0) }
+    }
+
+    /// The number of requests made so far, counting retries.
+    pub fn requests(&self) -> u32 {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    /// The series with every tag in `tags`, which are separated by `;` as in `loans;australia`.
+    pub fn tags_series(&self, tags: &str) -> Result<Seriess> {
+        self.get("tags/series", &[("tag_names", tags)])
+    }
+
+    /// The metadata of a series.
+    pub fn series(&self, series_id: &str) -> Result<Seriess> {
+        self.get("series", &[("series_id", series_id)])
+    }
+
+    /// The observations of a series.
+    pub fn observations(&self, series_id: &str) -> Result<Observations> {
+        self.get("series/observations", &[("series_id", series_id)])
+    }
+
+    /// Request `path` under the base URL with `params`, and parse the JSON response. The response
+    /// is read from and written to the cache, if there is one.
+    pub fn get<T: DeserializeOwned>(&self, path: &str, params: &[(&str, &str)]) -> Result<T> {
+        let cache = self.config.cache
+            .as_ref()
+            .map(|cache| (cache, cache.file(&self.config.base_url, path, params)));
+
+        if let Some((cache, file)) = &cache {
+            if let Some(body) = cache.read(file)? {
+                return parse(path, &body)
+            }
+            if cache.offline {
+                return Err(Error::DataSource(format!(
+                    "FRED [{}] is not cached at {} and the client is offline",
+                    path,
+                    file.display(),
+                )))
+            }
+        }
+
+        let body = self.request(path, params)?;
+        let value = parse(path, &body)?;
+        if let Some((cache, file)) = &cache {
+            cache.write(file, &body)?;
+        }
+        Ok(value)
+    }
+
+    // Request `path` from FRED, retrying failures which may pass, and return the body.
+    fn request(&self, path: &str, params: &[(&str, &str)]) -> Result<String> {
+        let url = format!("{}/{}", self.config.base_url.trim_end_matches('/'), path);
+
+        let mut attempt = 0;
+        loop {
+            self.spend()?;
+            self.limiter.acquire();
+
+            let mut request = self.agent
+                .get(&url)
+                .query("api_key", &self.config.api_key)
+                .query("file_type", "json");
+            for (key, value) in params {
+                request = request.query(key, value);
+            }
+
+            let (reason, retry_after) = match request.call() {
+                Ok(response) => return Ok(response.into_string()?),
+                Err(ureq::Error::Status(status, response)) if is_retryable(status) => {
+                    let retry_after = response
+                        .header("Retry-After")
+                        .and_then(|s| s.trim().parse().ok())
+                        .map(Duration::from_secs);
+                    (format!("status {}", status), retry_after)
+                },
+                Err(ureq::Error::Status(status, response)) => {
+                    return Err(Error::DataSource(format!(
+                        "FRED [{}] failed with status {}: {}",
+                        path,
+                        status,
+                        response.into_string().unwrap_or_default().trim(),
+                    )))
+                },
+                // The message of a transport error has the URL, which has the API key.
+                Err(ureq::Error::Transport(e)) => (format!("transport error: {}", e.kind()), None),
+            };
+
+            if attempt >= self.config.max_retries {
+                return Err(Error::DataSource(format!(
+                    "FRED [{}] failed after {} attempts: {}",
+                    path,
+                    attempt + 1,
+                    reason,
+                )))
+            }
+            let delay = retry_after.unwrap_or_else(|| {
+                backoff(attempt, self.config.base_delay, self.config.max_delay, jitter())
+            });
+            thread::sleep(delay.min(self.config.max_delay));
+            attempt += 1;
+        }
+    }
+
+    // Count a request against the budget, failing if it is spent.
+    fn spend(&self) -> Result<()> {
+        let budget = self.config.budget;
+        self.requests
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| match budget {
+                Some(budget) if n >= budget => None,
+                _ => Some(n + 1),
+            })
+            .map(|_| ())
+            .map_err(|n| Error::DataSource(format!("The budget of {} FRED requests is spent", n)))
+    }
+}
+
+// Parse the body of a response to `path`.
+fn parse<T: DeserializeOwned>(path: &str, body: &str) -> Result<T> {
+    serde_json::from_str(body)
+        .map_err(|e| Error::DataSource(format!("FRED [{}] sent bad JSON: {}", path, e)))
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread::JoinHandle,
+    };
+
+    const TAGS_SERIES: &str = r#"{"seriess":[{"id":"AUSURAMS","title":"Unemployment Rate","units":"Percent"}]}"#;
+
+    // Serve `responses` to one connection each, returning the URL of the server and a handle
+    // which gives the request line of each connection.
+    fn mock_server(responses: Vec<(u16, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 4096];
+                let n = stream.read(&mut buf).unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                requests.push(request.lines().next().unwrap_or_default().to_string());
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body,
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn config(base_url: String) -> FredConfig {
+        FredConfig {
+            base_url,
+            api_key:                "key".to_string(),
+            requests_per_second:    1000.0,
+            base_delay:             Duration::from_millis(1),
+            max_delay:              Duration::from_millis(5),
+            ..FredConfig::default()
+        }
+    }
+
+    #[test]
+    fn server_errors_should_be_retried() {
+        let (url, server) = mock_server(vec!((503, ""), (500, ""), (200, TAGS_SERIES)));
+        let client = FredClient::new(config(url));
+
+        let tags_series = client.tags_series("loans;australia").unwrap();
+        assert_eq!(tags_series.seriess[0].id, "AUSURAMS");
+        assert_eq!(client.requests(), 3);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /tags/series?api_key=key&file_type=json&tag_names=loans"));
+    }
+
+    #[test]
+    fn client_errors_should_not_be_retried() {
+        let (url, server) = mock_server(vec!((400, r#"{"error_message":"Bad Request"}"#)));
+        let client = FredClient::new(config(url));
+
+        let e = client.tags_series("loans;australia").unwrap_err();
+        assert!(matches!(e, Error::DataSource(_)));
+        assert!(e.to_string().contains("status 400"));
+        assert_eq!(client.requests(), 1);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn transport_errors_should_not_show_the_api_key() {
+        // Nothing listens on the port once the listener is dropped.
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let client = FredClient::new(FredConfig {
+            max_retries: 0,
+            ..config(format!("http://{}", addr))
+        });
+
+        let e = client.tags_series("loans;australia").unwrap_err();
+        assert!(matches!(e, Error::DataSource(_)));
+        assert!(!e.to_string().contains("api_key"));
+    }
+
+    #[test]
+    fn requests_should_stop_when_the_budget_is_spent() {
+        let (url, server) = mock_server(vec!((500, ""), (429, "")));
+        let client = FredClient::new(FredConfig { budget: Some(2), ..config(url) });
+
+        let e = client.tags_series("loans;australia").unwrap_err();
+        assert_eq!(e.to_string(), "Data source failed: The budget of 2 FRED requests is spent");
+        assert_eq!(client.requests(), 2);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn cached_responses_should_not_be_requested_again() {
+        let root = std::env::temp_dir().join("graphics_pipeline_fred_cache");
+        let _ = std::fs::remove_dir_all(&root);
+        let cache = ResponseCache::new(&root, cache::DEFAULT_TTL, false);
+
+        let (url, server) = mock_server(vec!((200, TAGS_SERIES)));
+        let client = FredClient::new(FredConfig {
+            cache: Some(cache.clone()),
+            ..config(url.clone())
+        });
+        client.tags_series("loans;australia").unwrap();
+        client.tags_series("loans;australia").unwrap();
+        assert_eq!(client.requests(), 1);
+        server.join().unwrap();
+
+        // Offline, cached responses are served and others fail without a request, although the
+        // server has gone.
+        let offline = ResponseCache { offline: true, ..cache };
+        let client = FredClient::new(FredConfig { cache: Some(offline), ..config(url) });
+        assert_eq!(client.tags_series("loans;australia").unwrap().seriess[0].id, "AUSURAMS");
+        assert!(client.tags_series("loans;canada").is_err());
+        assert_eq!(client.requests(), 0);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn tokens_should_refill_up_to_the_burst() {
+        let bucket = TokenBucket::new(4.0, 2);
+        let now = Instant::now();
+        for _ in 0..4 {
+            bucket.reserve(now);
+        }
+        // Two tokens are reserved past `now`, so the next is 750ms away.
+        assert_eq!(bucket.reserve(now), Duration::from_millis(750));
+
+        // After a quiet period the bucket is full but holds no more than the burst.
+        let later = now + Duration::from_secs(10);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+        assert_eq!(bucket.reserve(later), Duration::from_millis(250));
+    }
+}
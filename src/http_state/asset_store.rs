@@ -0,0 +1,120 @@
+//! A generic in-memory store for static files, such as CSS, the favicon and HTML templates.
+//!
+//! ```ignore
+//! let css = AssetStore::new(TSCss, &data_root)?;
+//! let response = css.get("style.css".to_string());
+//! ```
+
+use crate::{
+    error::Result,
+    file_resources::{file_name, from_path_arg, IntoResources},
+    http_state::{HttpRequest, HttpResponse, HttpState},
+    http_state::encoded::EncodedBody,
+    http_state::hot_reload::Reload,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Return the content type of a file from its extension.
+/// ```
+/// # use std::path::Path;
+/// # use graphics_pipeline::http_state::asset_store::content_type;
+/// assert_eq!(content_type(Path::new("style.css")), "text/css");
+/// ```
+pub fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|os_str| os_str.to_str()) {
+        Some("css")     => "text/css",
+        Some("html")    => "text/html; charset=utf-8",
+        Some("js")      => "text/javascript",
+        Some("json")    => "application/json",
+        Some("png")     => "image/png",
+        Some("svg")     => "image/svg+xml",
+        Some("ico")     => "image/x-icon",
+        Some("keytree") => "text/plain; charset=utf-8",
+        Some("csv")     => "text/csv",
+        _               => "application/octet-stream",
+    }
+}
+
+// === AssetStore =================================================================================
+
+/// All the files of a resource type, keyed by file name with extension, such as `style.css`.
+pub struct AssetStore<R> {
+    resource: R,
+    assets: HashMap<String, EncodedBody>,
+}
+
+impl<R: IntoResources> AssetStore<R> {
+
+    /// Load every file of the resource type into memory.
+    pub fn new<P: AsRef<Path>>(resource: R, data_root: P) -> Result<Self> {
+        let root: PathBuf = from_path_arg(data_root);
+
+        let mut assets = HashMap::new();
+        for path in resource.into_resources(&root)?.iter() {
+            let key = file_name(&path)?.to_string();
+            let body = EncodedBody::new(content_type(&path), fs::read(&path)?)?;
+            assets.insert(key, body);
+        }
+        Ok(AssetStore { resource, assets })
+    }
+}
+
+impl<R> AssetStore<R> {
+
+    /// Return the file contents.
+    pub fn asset(&self, file: &str) -> Option<&EncodedBody> {
+        self.assets.get(file)
+    }
+
+    /// Return the file names in the store.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.assets.keys()
+    }
+}
+
+impl<R: IntoResources + Clone> Reload for AssetStore<R> {
+    fn reload(&self, data_root: &Path) -> Result<Self> {
+        AssetStore::new(self.resource.clone(), data_root)
+    }
+
+    fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>> {
+        Ok(vec!(self.resource.dir(data_root)?))
+    }
+}
+
+impl<R> HttpState for AssetStore<R> {
+    /// The filename with the extension
+    type Key = String;
+
+    fn get(&self, key: String) -> HttpResponse {
+        match self.assets.get(&key) {
+            Some(body) => body.response(),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    fn respond(&self, key: String, req: &HttpRequest) -> HttpResponse {
+        match self.assets.get(&key) {
+            Some(body) => body.respond(req),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn content_type_should_be_inferred_from_extension() {
+        assert_eq!(content_type(Path::new("favicon.png")), "image/png");
+        assert_eq!(content_type(Path::new("page.html")), "text/html; charset=utf-8");
+        assert_eq!(content_type(Path::new("README")), "application/octet-stream");
+    }
+}
@@ -0,0 +1,290 @@
+//! Response bodies with precomputed `ETag`s and compressed variants.
+//!
+//! Pages pull in dozens of graphics scripts and series files, so each body is hashed and
+//! compressed once when the store is loaded rather than on every request. Responses carry
+//! `Cache-Control: no-cache`, so browsers revalidate with `If-None-Match` and are answered with
+//! `304 Not Modified` while the file is unchanged. This keeps hot-reloaded files fresh.
+
+use crate::{
+    error::Result,
+    http_state::HttpResponse,
+    manifest::checksum,
+};
+use actix_web::{http::header, HttpRequest};
+use flate2::{write::GzEncoder, Compression};
+use std::io::Write;
+
+pub const CACHE_CONTROL: &str = "public, no-cache";
+
+// === Encoding ===================================================================================
+
+/// A content encoding that a store can respond with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+impl Encoding {
+
+    /// Choose the preferred encoding from an `Accept-Encoding` header, preferring brotli over
+    /// gzip. Encodings with `q=0` are refused.
+    /// ```
+    /// # use graphics_pipeline::http_state::encoded::Encoding;
+    /// assert_eq!(Encoding::negotiate(Some("gzip, deflate, br")), Encoding::Brotli);
+    /// assert_eq!(Encoding::negotiate(Some("gzip, br;q=0")), Encoding::Gzip);
+    /// assert_eq!(Encoding::negotiate(None), Encoding::Identity);
+    /// ```
+    pub fn negotiate(accept_encoding: Option<&str>) -> Self {
+        let accepted: Vec<&str> = match accept_encoding {
+            Some(s) => {
+                s.split(',')
+                    .filter_map(|item| {
+                        let mut parts = item.split(';');
+                        let name = parts.next()?.trim();
+                        let refused = parts.any(|param| {
+                            let param = param.trim();
+                            param.starts_with("q=") &&
+                            param[2..].parse::<f32>().map(|q| q == 0.0).unwrap_or(false)
+                        });
+                        match refused {
+                            true => None,
+                            false => Some(name),
+                        }
+                    })
+                    .collect()
+            },
+            None => Vec::new(),
+        };
+
+        if accepted.contains(&"br") {
+            Encoding::Brotli
+        } else if accepted.contains(&"gzip") || accepted.contains(&"*") {
+            Encoding::Gzip
+        } else {
+            Encoding::Identity
+        }
+    }
+}
+
+// === EncodedBody ================================================================================
+
+/// A response body, its `ETag`, and its compressed variants where compression makes it smaller.
+#[derive(Clone, Debug)]
+pub struct EncodedBody {
+    pub content_type:   &'static str,
+
+    /// The `ETag` of the uncompressed body.
+    pub etag:           String,
+    identity:           Vec<u8>,
+    gzip:               Option<Vec<u8>>,
+    brotli:             Option<Vec<u8>>,
+}
+
+impl EncodedBody {
+
+    /// Hash and compress a body. Images are already compressed so are stored as they are.
+    pub fn new(content_type: &'static str, body: Vec<u8>) -> Result<Self> {
+        let etag = format!("\"{}\"", &checksum(&body)[..32]);
+
+        let (gzip, brotli) = match is_compressible(content_type) {
+            true => {
+                let gzip = gzip(&body)?;
+                let brotli = brotli(&body)?;
+                (
+                    Some(gzip).filter(|v| v.len() < body.len()),
+                    Some(brotli).filter(|v| v.len() < body.len()),
+                )
+            },
+            false => (None, None),
+        };
+
+        Ok(
+            EncodedBody {
+                content_type,
+                etag,
+                identity: body,
+                gzip,
+                brotli,
+            }
+        )
+    }
+
+    /// The uncompressed body.
+    pub fn bytes(&self) -> &[u8] {
+        &self.identity
+    }
+
+    /// Respond with the uncompressed body.
+    pub fn response(&self) -> HttpResponse {
+        self.respond_with(Encoding::Identity)
+    }
+
+    /// The `ETag` of the body in an encoding. Each encoding has its own strong `ETag`, the
+    /// identity `ETag` suffixed with the content encoding, since the bytes differ.
+    /// ```
+    /// # use graphics_pipeline::http_state::encoded::{EncodedBody, Encoding};
+    /// let body = EncodedBody::new("text/css", "body {}\n".repeat(100).into_bytes()).unwrap();
+    /// assert_eq!(body.etag_for(Encoding::Identity), body.etag);
+    /// assert!(body.etag_for(Encoding::Gzip).ends_with("-gzip\""));
+    /// ```
+    pub fn etag_for(&self, encoding: Encoding) -> String {
+        match self.variant(encoding).1 {
+            Some(content_encoding) => {
+                format!("{}-{}\"", self.etag.trim_end_matches('"'), content_encoding)
+            },
+            None => self.etag.clone(),
+        }
+    }
+
+    /// Respond with `304 Not Modified` if the request's `If-None-Match` matches the `ETag` of the
+    /// best encoding the request accepts, otherwise with the body in that encoding.
+    pub fn respond(&self, req: &HttpRequest) -> HttpResponse {
+        let accept_encoding = req.headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok());
+        let encoding = Encoding::negotiate(accept_encoding);
+
+        let if_none_match = req.headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok());
+
+        if let Some(tags) = if_none_match {
+            let etag = self.etag_for(encoding);
+            if etag_matches(tags, &etag) {
+                return HttpResponse::NotModified()
+                    .insert_header((header::ETAG, etag))
+                    .insert_header((header::CACHE_CONTROL, CACHE_CONTROL))
+                    .insert_header((header::VARY, "Accept-Encoding"))
+                    .finish()
+            }
+        }
+        self.respond_with(encoding)
+    }
+
+    fn respond_with(&self, encoding: Encoding) -> HttpResponse {
+        let (body, content_encoding) = self.variant(encoding);
+
+        let mut builder = HttpResponse::Ok();
+        builder
+            .content_type(self.content_type)
+            .insert_header((header::ETAG, self.etag_for(encoding)))
+            .insert_header((header::CACHE_CONTROL, CACHE_CONTROL))
+            .insert_header((header::VARY, "Accept-Encoding"));
+
+        if let Some(content_encoding) = content_encoding {
+            builder.insert_header((header::CONTENT_ENCODING, content_encoding));
+        }
+        builder.body(body.clone())
+    }
+
+    // The body and its content encoding in the best stored variant for `encoding`.
+    fn variant(&self, encoding: Encoding) -> (&Vec<u8>, Option<&'static str>) {
+        match (encoding, &self.brotli, &self.gzip) {
+            (Encoding::Brotli, Some(br), _) => (br, Some("br")),
+            (Encoding::Brotli, None, Some(gz)) => (gz, Some("gzip")),
+            (Encoding::Gzip, _, Some(gz)) => (gz, Some("gzip")),
+            _ => (&self.identity, None),
+        }
+    }
+}
+
+// Returns true if an `If-None-Match` header value matches the `ETag`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    content_type.starts_with("text/") ||
+    content_type.starts_with("application/json") ||
+    content_type.starts_with("image/svg+xml")
+}
+
+fn gzip(body: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(body)?;
+    Ok(encoder.finish()?)
+}
+
+fn brotli(body: &[u8]) -> Result<Vec<u8>> {
+    let mut acc = Vec::new();
+    {
+        let mut writer = brotli::CompressorWriter::new(&mut acc, 4096, 11, 22);
+        writer.write_all(body)?;
+    }
+    Ok(acc)
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    fn js() -> EncodedBody {
+        EncodedBody::new("text/javascript", "some js\n".repeat(100).into_bytes()).unwrap()
+    }
+
+    #[test]
+    fn matching_etag_should_return_not_modified() {
+        let body = js();
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, body.etag.clone()))
+            .to_http_request();
+        assert_eq!(body.respond(&req).status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    fn stale_etag_should_return_body() {
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "\"stale\""))
+            .to_http_request();
+        assert_eq!(js().respond(&req).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn accepted_encoding_should_be_used() {
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_http_request();
+        let response = js().respond(&req);
+        assert_eq!(response.headers().get(header::CONTENT_ENCODING).unwrap(), "gzip");
+    }
+
+    #[test]
+    fn images_should_not_be_compressed() {
+        let body = EncodedBody::new("image/png", vec!(0; 1000)).unwrap();
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT_ENCODING, "br, gzip"))
+            .to_http_request();
+        assert!(body.respond(&req).headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[test]
+    fn etag_of_one_encoding_should_not_match_another() {
+        let body = js();
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, body.etag.clone()))
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_http_request();
+        let response = body.respond(&req);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::ETAG).unwrap().to_str().unwrap(),
+            body.etag_for(Encoding::Gzip),
+        );
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, body.etag_for(Encoding::Gzip)))
+            .insert_header((header::ACCEPT_ENCODING, "gzip"))
+            .to_http_request();
+        let response = body.respond(&req);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::VARY).unwrap(), "Accept-Encoding");
+    }
+}
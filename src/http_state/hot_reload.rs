@@ -0,0 +1,229 @@
+//! Rebuild an `HttpState` store when the files it was loaded from change.
+//!
+//! `HotReload` watches the directories a store was loaded from (using inotify on Linux) and
+//! rebuilds the store when a file is created, modified or removed. The new store is swapped in
+//! atomically, so requests are answered from the previous store until the rebuild is complete.
+//! If the rebuild fails, for example because a spec is half-edited, the previous store is kept.
+//!
+//! ```ignore
+//! let js = HotReload::new(JsScripts::new(&data_root)?, &data_root)?;
+//! let response = js.get("graphic".parse()?);
+//! ```
+
+use crate::{
+    error::{Error, Result},
+    file_resources::from_path_arg,
+    http_state::{HttpRequest, HttpResponse, HttpState},
+};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+// === Reload =====================================================================================
+
+/// A store that can be rebuilt from the files in the data root.
+pub trait Reload: Sized {
+
+    /// Build a new store from the files in the data root.
+    fn reload(&self, data_root: &Path) -> Result<Self>;
+
+    /// The directories that the store is loaded from, which are watched recursively so that new
+    /// subdirectories, such as a new country under `raw_data/`, are picked up.
+    fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>>;
+}
+
+// === HotReload ==================================================================================
+
+/// Wraps a store, rebuilding it when the files under its watched directories change.
+pub struct HotReload<S> {
+    state: Arc<RwLock<Arc<S>>>,
+
+    // The watcher stops when dropped.
+    _watcher: RecommendedWatcher,
+}
+
+impl<S> HotReload<S> {
+
+    /// The current store. The store is not affected by later reloads.
+    pub fn current(&self) -> Arc<S> {
+        match self.state.read() {
+            Ok(guard) => Arc::clone(&guard),
+            Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+        }
+    }
+}
+
+impl<S: Reload + Send + Sync + 'static> HotReload<S> {
+
+    /// Start watching the directories of `store`.
+    pub fn new<P: AsRef<Path>>(store: S, data_root: P) -> Result<Self> {
+        let root: PathBuf = from_path_arg(data_root);
+        let dirs = store.watch_dirs(&root)?;
+
+        let state = Arc::new(RwLock::new(Arc::new(store)));
+        let shared = Arc::clone(&state);
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+            match res {
+                Ok(event) if is_change(&event) => reload(&shared, &root),
+                Ok(_) => {},
+                Err(e) => eprintln!("Failed to watch files: {}", e),
+            }
+        })
+        .map_err(|e| Error::Other(e.into()))?;
+
+        // Recursive, so that spec fragments and new data buckets in subdirectories are watched.
+        for dir in dirs.iter() {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .map_err(|e| Error::Other(e.into()))?;
+        }
+
+        Ok(HotReload { state, _watcher: watcher })
+    }
+}
+
+impl<S: HttpState> HttpState for HotReload<S> {
+    type Key = S::Key;
+
+    fn get(&self, key: Self::Key) -> HttpResponse {
+        self.current().get(key)
+    }
+
+    fn respond(&self, key: Self::Key, req: &HttpRequest) -> HttpResponse {
+        self.current().respond(key, req)
+    }
+}
+
+// === MaybeReload ================================================================================
+
+/// A store which is either loaded once, or hot reloaded when its files change.
+pub enum MaybeReload<S> {
+    Once(S),
+    Watched(HotReload<S>),
+}
+
+impl<S: Reload + Send + Sync + 'static> MaybeReload<S> {
+
+    /// Watch the store's directories if `watch` is true.
+    pub fn new<P: AsRef<Path>>(store: S, data_root: P, watch: bool) -> Result<Self> {
+        match watch {
+            true => Ok(MaybeReload::Watched(HotReload::new(store, data_root)?)),
+            false => Ok(MaybeReload::Once(store)),
+        }
+    }
+}
+
+impl<S: HttpState> HttpState for MaybeReload<S> {
+    type Key = S::Key;
+
+    fn get(&self, key: Self::Key) -> HttpResponse {
+        match self {
+            MaybeReload::Once(store) => store.get(key),
+            MaybeReload::Watched(store) => store.get(key),
+        }
+    }
+
+    fn respond(&self, key: Self::Key, req: &HttpRequest) -> HttpResponse {
+        match self {
+            MaybeReload::Once(store) => store.respond(key, req),
+            MaybeReload::Watched(store) => store.respond(key, req),
+        }
+    }
+}
+
+// Returns true if the event changed the contents of a watched directory.
+fn is_change(event: &Event) -> bool {
+    event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove()
+}
+
+// Rebuild the store and swap it in, or keep the previous store if the rebuild fails.
+fn reload<S: Reload>(state: &RwLock<Arc<S>>, data_root: &Path) {
+    let current = match state.read() {
+        Ok(guard) => Arc::clone(&guard),
+        Err(poisoned) => Arc::clone(&poisoned.into_inner()),
+    };
+    match current.reload(data_root) {
+        Ok(store) => {
+            match state.write() {
+                Ok(mut guard) => *guard = Arc::new(store),
+                Err(poisoned) => *poisoned.into_inner() = Arc::new(store),
+            }
+        },
+        Err(e) => eprintln!("Failed to reload, keeping previous state: {}", e),
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::{fs, thread, time};
+
+    // Counts the files under a directory.
+    struct FileCount(usize);
+
+    fn count_files(dir: &Path) -> Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            count += if path.is_dir() { count_files(&path)? } else { 1 };
+        }
+        Ok(count)
+    }
+
+    impl Reload for FileCount {
+        fn reload(&self, data_root: &Path) -> Result<Self> {
+            Ok(FileCount(count_files(data_root)?))
+        }
+
+        fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>> {
+            Ok(vec!(data_root.to_path_buf()))
+        }
+    }
+
+    // A directory of its own for each test process.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("graphics_pipeline_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Wait for the store to count `expected` files.
+    fn wait_for(store: &HotReload<FileCount>, expected: usize) -> usize {
+        let mut count = 0;
+        for _ in 0..20 {
+            thread::sleep(time::Duration::from_millis(100));
+            count = store.current().0;
+            if count == expected { break }
+        }
+        count
+    }
+
+    #[test]
+    fn store_should_reload_when_file_is_added() {
+        let dir = test_dir("hot_reload");
+        let store = HotReload::new(FileCount(0), &dir).unwrap();
+        fs::write(dir.join("test.js"), "some js\n").unwrap();
+
+        assert_eq!(wait_for(&store, 1), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn store_should_reload_when_file_is_added_to_new_subdirectory() {
+        let dir = test_dir("hot_reload_subdirectory");
+        let store = HotReload::new(FileCount(0), &dir).unwrap();
+        fs::create_dir_all(dir.join("u/australia")).unwrap();
+        thread::sleep(time::Duration::from_millis(100));
+        fs::write(dir.join("u/australia/AUSURAMS.csv"), "DATE,VALUE\n").unwrap();
+
+        assert_eq!(wait_for(&store, 1), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,19 @@
 //! Provides a trait for a datastore that responds to a key with an actix_web::HttpResponse.
 
-pub use actix_web::HttpResponse;
+pub mod asset_store;
+pub mod encoded;
+pub mod hot_reload;
+
+pub use actix_web::{HttpRequest, HttpResponse};
 
 pub trait HttpState {
     type Key;
     
     fn get(&self, key: Self::Key) -> HttpResponse;
+
+    /// Respond to a request, using request headers such as `If-None-Match` and `Accept-Encoding`.
+    /// Stores which do not use the request can rely on the default, which calls `get`.
+    fn respond(&self, key: Self::Key, _req: &HttpRequest) -> HttpResponse {
+        self.get(key)
+    }
 }
@@ -1,34 +1,68 @@
 
+/// Rebuild only the stages of the pipeline whose inputs have changed.
+pub mod build;
+
+/// The `gp` command-line tool.
+pub mod cli;
+
+/// The configuration of the pipeline from a file and the environment.
+pub mod config;
+
 pub mod countries;
 pub mod data_transforms;
+
+/// Find files on disk which have drifted from the specifications.
+pub mod drift;
+
+/// The errors of the library.
+pub mod error;
+pub use error::{Error, Result};
+
+/// Export time-series pages as a static site.
+pub mod export;
+
 pub mod file_resources;
 pub mod filter_spec;
 pub mod filter_to_series;
 
-/// Implement actix web data for html. 
-pub mod html;
+/// A FRED client with rate limiting, retries and a request budget.
+pub mod fred_client;
 
 pub mod http_state;
 
+/// Checksums of every file in the data root.
+pub mod manifest;
+
 pub mod meta_data;
 
 pub mod os_setup;
 
-// pub mod page_spec;
+/// The pages of the time-series graphics, as specified in `ts_graphics/spec`.
+pub mod page_spec;
 
 pub mod primitives;
 
+/// The `shared_http` server.
+pub mod server;
+
+/// Compose specs from several keytree files with includes.
+pub mod spec_files;
+
 /// KeyTree wrapper for `series_spec.keytree`.
 pub mod series_spec;
 
 /// Use `series_spec.keytree` to write retrieve csv data from FRED and save to disk.  
 pub mod series_to_disk;
 
+/// Use `series_spec.keytree` to write the FRED metadata of each series to disk.
 pub mod series_to_meta;
 
 pub mod ts_graphics;
 
 pub mod ui_spec;
 
+/// Run batches of work on a bounded pool of threads.
+pub mod workers;
+
 // pub mod ui_to_server;
 
@@ -0,0 +1,435 @@
+//! A manifest of the files in the data root, recording the size and checksum of every file
+//! reachable through [`IntoResources`](../file_resources/trait.IntoResources.html). A manifest
+//! saved at deployment can be verified later to find files in `raw_data/` or `transformed_data/`
+//! that have changed, gone missing, or no longer belong to `series_spec.keytree`.
+//!
+//! ```text
+//! manifest:
+//!     file:
+//!         path:       raw_data/u/australia/AUSURAMS.csv
+//!         size:       4182
+//!         checksum:   9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//!         series_id:  AUSURAMS
+//! ```
+
+use anyhow::anyhow;
+use crate::{
+    error::{Error, Result},
+    file_resources::{data_buckets, from_path_arg, IntoResources},
+    file_resources::impls::{
+        CsvRawData,
+        CsvTransformedData,
+        MetaData,
+        PidGraphicCss,
+        PidGraphicsFavIcon,
+        PidGraphicsJs,
+        Spec,
+        TSCss,
+        TSGraphicsJs,
+        TSHtmlTemplate,
+        TSPageSpec,
+    },
+    primitives::SeriesId,
+    series_to_disk::{spec_map_from_spec, SeriesSpecMap},
+};
+use key_tree::{KeyTree, KeyTreeError};
+use key_tree::serialize::{IntoKeyTree, KeyTreeString};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The manifest is saved in the data root under this name.
+pub const MANIFEST_FILE: &str = "manifest.keytree";
+
+/// Return the hex-encoded SHA-256 checksum of some bytes.
+/// ```
+/// # use graphics_pipeline::manifest::checksum;
+/// assert_eq!(
+///     checksum(b"abc"),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+/// );
+/// ```
+pub fn checksum(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Build a manifest of the data root and save it as `manifest.keytree`.
+pub fn write_manifest<P, S>(data_root: P, series_spec_file: S) -> Result<()>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let root: PathBuf = from_path_arg(data_root);
+    Manifest::build(&root, series_spec_file)?.save(&root)
+}
+
+/// Checks the data root against the saved manifest, displays results and returns the issues.
+pub fn verify_manifest<P, S>(data_root: P, series_spec_file: S) -> Result<Vec<ManifestIssue>>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let root: PathBuf = from_path_arg(data_root);
+    let manifest = Manifest::from_file(&root)?;
+    let issues = manifest.verify(&root, series_spec_file)?;
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if issues.is_empty() {
+        println!(" ok  {} files", manifest.len());
+    }
+    Ok(issues)
+}
+
+// === ManifestEntry ==============================================================================
+
+/// The record of a single file. The path is relative to the data root.
+/// ```
+/// # use key_tree::KeyTree;
+/// # use graphics_pipeline::manifest::ManifestEntry;
+/// # let s = "
+///     file:
+///         path:       raw_data/u/australia/AUSURAMS.csv
+///         size:       4182
+///         checksum:   9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+///         series_id:  AUSURAMS
+/// # ";
+/// let entry: ManifestEntry = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+/// # assert_eq!(entry.size, 4182);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub path:       PathBuf,
+    pub size:       u64,
+    pub checksum:   String,
+    pub series_id:  Option<SeriesId>,
+}
+
+impl ManifestEntry {
+
+    /// Read a file and record its size and checksum. `path` must be inside `data_root`.
+    pub fn from_file(data_root: &Path, path: &Path, series_id: Option<SeriesId>) -> Result<Self> {
+        let bytes = fs::read(path).map_err(|_| Error::FileNotFound {
+            file:   path.file_name().map(PathBuf::from).unwrap_or_default(),
+            dir:    path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        })?;
+        let relative = path
+            .strip_prefix(data_root)
+            .map_err(|_| anyhow!("File '{}' is not in '{}'", path.display(), data_root.display()))?;
+
+        Ok(
+            ManifestEntry {
+                path:       relative.to_path_buf(),
+                size:       bytes.len() as u64,
+                checksum:   checksum(&bytes),
+                series_id,
+            }
+        )
+    }
+}
+
+impl TryInto<ManifestEntry> for KeyTree {
+    type Error = KeyTreeError;
+
+    fn try_into(self) -> std::result::Result<ManifestEntry, Self::Error> {
+        let path: String = self.from_str("file::path")?;
+        Ok(
+            ManifestEntry {
+                path:       PathBuf::from(path),
+                size:       self.from_str("file::size")?,
+                checksum:   self.from_str("file::checksum")?,
+                series_id:  self.opt_from_str("file::series_id")?,
+            }
+        )
+    }
+}
+
+impl IntoKeyTree for ManifestEntry {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "file");
+        kt.push_keyvalue(1, "path", self.path.display());
+        kt.push_keyvalue(1, "size", self.size);
+        kt.push_keyvalue(1, "checksum", &self.checksum);
+        if let Some(series_id) = &self.series_id {
+            kt.push_keyvalue(1, "series_id", series_id);
+        }
+        kt
+    }
+}
+
+// === ManifestIssue ==============================================================================
+
+/// A difference between the manifest, the data root and the series specification.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ManifestIssue {
+
+    /// The size or checksum of the file has changed.
+    Tampered(PathBuf),
+
+    /// The file is in the manifest but not on disk.
+    Missing(PathBuf),
+
+    /// The file is on disk but not in the manifest.
+    Unlisted(PathBuf),
+
+    /// A CSV file whose series is not in the series specification.
+    OrphanCsv(PathBuf),
+
+    /// A series in the series specification without a CSV file in `raw_data/`.
+    MissingCsv(SeriesId),
+}
+
+impl fmt::Display for ManifestIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestIssue::Tampered(path)       => write!(f, "tampered  {}", path.display()),
+            ManifestIssue::Missing(path)        => write!(f, "missing   {}", path.display()),
+            ManifestIssue::Unlisted(path)       => write!(f, "unlisted  {}", path.display()),
+            ManifestIssue::OrphanCsv(path)      => write!(f, "orphan    {}", path.display()),
+            ManifestIssue::MissingCsv(series_id) => write!(f, "no csv    {}", series_id),
+        }
+    }
+}
+
+// === Manifest ===================================================================================
+
+/// All files in the data root, ordered by path.
+#[derive(Debug, PartialEq)]
+pub struct Manifest(BTreeMap<PathBuf, ManifestEntry>);
+
+impl Manifest {
+
+    /// Hash every file in the data root that is reachable through `IntoResources`.
+    pub fn build<P, S>(data_root: P, series_spec_file: S) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+    {
+        let root: PathBuf = canonical_root(data_root)?;
+        let spec_map = spec_map_from_spec(&root, series_spec_file)?;
+
+        let mut acc = Vec::new();
+        for path in files_on_disk(&root)? {
+            acc.push(ManifestEntry::from_file(&root, &path, series_id_of(&path, &spec_map))?);
+        }
+        Ok(acc.into_iter().collect())
+    }
+
+    /// Read `manifest.keytree` from the data root.
+    pub fn from_file<P: AsRef<Path>>(data_root: P) -> Result<Self> {
+        let path = from_path_arg(data_root).join(MANIFEST_FILE);
+        let entries: Vec<ManifestEntry> = KeyTree::parse(&path)?.opt_vec_at("manifest::file")?;
+        Ok(entries.into_iter().collect())
+    }
+
+    /// Write `manifest.keytree` to the data root.
+    pub fn save<P: AsRef<Path>>(&self, data_root: P) -> Result<()> {
+        let path = from_path_arg(data_root).join(MANIFEST_FILE);
+        fs::write(&path, self.keytree().to_string())
+            .map_err(|_| anyhow!("Failed to write '{}'", path.display()).into())
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.0.get(path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.0.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Compare the manifest to the files on disk and to the series specification, returning every
+    /// difference in path order.
+    pub fn verify<P, S>(&self, data_root: P, series_spec_file: S) -> Result<Vec<ManifestIssue>>
+    where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+    {
+        let root: PathBuf = canonical_root(data_root)?;
+        let spec_map = spec_map_from_spec(&root, series_spec_file)?;
+
+        let mut issues = Vec::new();
+        let mut on_disk = BTreeMap::new();
+
+        for path in files_on_disk(&root)? {
+            let entry = ManifestEntry::from_file(&root, &path, series_id_of(&path, &spec_map))?;
+            on_disk.insert(entry.path.clone(), entry);
+        }
+
+        for (path, entry) in self.0.iter() {
+            match on_disk.get(path) {
+                Some(found) => {
+                    if found.size != entry.size || found.checksum != entry.checksum {
+                        issues.push(ManifestIssue::Tampered(path.clone()))
+                    }
+                },
+                None => issues.push(ManifestIssue::Missing(path.clone())),
+            }
+        }
+
+        for (path, entry) in on_disk.iter() {
+            if !self.0.contains_key(path) {
+                issues.push(ManifestIssue::Unlisted(path.clone()))
+            }
+            if entry.series_id.is_none() && is_data_csv(path) {
+                issues.push(ManifestIssue::OrphanCsv(path.clone()))
+            }
+        }
+
+        for series_spec in spec_map.iter() {
+            let csv_raw_data = CsvRawData {
+                country: series_spec.country(),
+                data_type: series_spec.data_type(),
+            };
+            let filename = PathBuf::from(series_spec.series_id().to_string()).with_extension("csv");
+            if !csv_raw_data.has_file(&root, &filename).unwrap_or(false) {
+                issues.push(ManifestIssue::MissingCsv(series_spec.series_id()))
+            }
+        }
+        Ok(issues)
+    }
+}
+
+impl FromIterator<ManifestEntry> for Manifest {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = ManifestEntry>
+    {
+        Manifest(iter.into_iter().map(|entry| (entry.path.clone(), entry)).collect())
+    }
+}
+
+impl IntoKeyTree for Manifest {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "manifest");
+        for entry in self.0.values() {
+            kt.push_keytree(1, entry.keytree());
+        }
+        kt
+    }
+}
+
+// === Helpers ====================================================================================
+
+fn canonical_root<P: AsRef<Path>>(data_root: P) -> Result<PathBuf> {
+    let root: PathBuf = from_path_arg(data_root);
+    root
+        .canonicalize()
+        .map_err(|_| Error::DirectoryNotFound(root.clone()))
+}
+
+// Returns true if the path is a CSV file under `raw_data/` or `transformed_data/`.
+fn is_data_csv(path: &Path) -> bool {
+    (path.starts_with("raw_data") || path.starts_with("transformed_data")) &&
+    path.extension() == Some("csv".as_ref())
+}
+
+// The `SeriesId` of a data file, if the series is in the specification. Transformed data files
+// like `LRHUTTTTAUA156N_a.csv` belong to the series `LRHUTTTTAUA156N`.
+fn series_id_of(path: &Path, spec_map: &SeriesSpecMap) -> Option<SeriesId> {
+    let stem = path.file_stem()?.to_str()?;
+    let series_id = SeriesId::new(stem);
+    match spec_map.contains(&series_id.stem()) {
+        true => Some(series_id),
+        false => None,
+    }
+}
+
+// Add the resources of one resource type to `acc`, ignoring resource types whose directory does
+// not exist in this data root.
+fn push_resources<R: IntoResources>(resource: &R, root: &Path, acc: &mut Vec<PathBuf>) -> Result<()> {
+    if resource.dir(root).is_err() {
+        return Ok(())
+    }
+    acc.extend(resource.into_resources(root)?.iter());
+    Ok(())
+}
+
+// Every file in the data root which is reachable through an `IntoResources` implementation.
+fn files_on_disk(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut acc = Vec::new();
+
+    push_resources(&Spec, root, &mut acc)?;
+    push_resources(&PidGraphicCss, root, &mut acc)?;
+    push_resources(&PidGraphicsFavIcon, root, &mut acc)?;
+    push_resources(&PidGraphicsJs, root, &mut acc)?;
+    push_resources(&TSPageSpec, root, &mut acc)?;
+    push_resources(&TSGraphicsJs, root, &mut acc)?;
+    push_resources(&TSHtmlTemplate, root, &mut acc)?;
+    push_resources(&TSCss, root, &mut acc)?;
+
+    if root.join("raw_data").is_dir() {
+        for (data_type, country) in data_buckets(root, "raw_data")? {
+            push_resources(&CsvRawData { country, data_type }, root, &mut acc)?;
+            push_resources(&MetaData { country, data_type }, root, &mut acc)?;
+        }
+    }
+
+    if root.join("transformed_data").is_dir() {
+        for (data_type, country) in data_buckets(root, "transformed_data")? {
+            push_resources(&CsvTransformedData { country, data_type }, root, &mut acc)?;
+        }
+    }
+
+    // Single-file resources such as `style.css` are listed whether or not they exist.
+    acc.retain(|path| path.is_file());
+    Ok(acc)
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_entry_should_round_trip_through_keytree() {
+        let entry = ManifestEntry {
+            path:       PathBuf::from("raw_data/u/australia/AUSURAMS.csv"),
+            size:       12,
+            checksum:   checksum(b"DATE,VALUE\n"),
+            series_id:  Some(SeriesId::new("AUSURAMS")),
+        };
+        let s = entry.keytree().to_string();
+        let output: ManifestEntry = KeyTree::parse_str(&s).unwrap().try_into().unwrap();
+        assert_eq!(output, entry);
+    }
+
+    #[test]
+    fn manifest_should_be_ordered_by_path() {
+        let entry = |p: &str| ManifestEntry {
+            path:       PathBuf::from(p),
+            size:       0,
+            checksum:   checksum(b""),
+            series_id:  None,
+        };
+        let manifest: Manifest = vec!(entry("specs/b.keytree"), entry("raw_data/a.csv"))
+            .into_iter()
+            .collect();
+        let mut iter = manifest.iter();
+        assert_eq!(iter.next().unwrap().path, PathBuf::from("raw_data/a.csv"));
+        assert_eq!(iter.next().unwrap().path, PathBuf::from("specs/b.keytree"));
+    }
+
+    #[test]
+    fn data_csv_should_be_recognised() {
+        assert!(is_data_csv(Path::new("raw_data/u/australia/AUSURAMS.csv")));
+        assert!(!is_data_csv(Path::new("raw_data/u/australia/AUSURAMS.meta")));
+        assert!(!is_data_csv(Path::new("pid_graphics/css/style.css")));
+    }
+}
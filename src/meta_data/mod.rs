@@ -1,9 +1,11 @@
 use crate::{
     // FromFile,
+    fred_client::SeriesItem,
     primitives::{
         SeriesId,
     }
 };
+use serde::Serialize;
 use key_tree::{
     KeyTree,
     KeyTreeError,
@@ -40,7 +42,7 @@ use key_tree::{
 /// # ";
 /// #   let _: Series = KeyTree::parse_str(spec).unwrap().try_into().unwrap();
 /// ```
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Series {
     realtime: String,
     series_id: SeriesId,
@@ -51,6 +53,30 @@ pub struct Series {
     seasonal_adjustment: String,
 }  
 
+impl Series {
+    pub fn series_id(&self) -> &SeriesId {
+        &self.series_id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+}
+
+impl From<&SeriesItem> for Series {
+    fn from(item: &SeriesItem) -> Self {
+        Series {
+            realtime:               item.realtime_start.clone(),
+            series_id:              SeriesId::new(&item.id),
+            title:                  item.title.clone(),
+            observation_start:      item.observation_start.clone(),
+            observation_end:        item.observation_end.clone(),
+            frequency:              item.frequency.clone(),
+            seasonal_adjustment:    item.seasonal_adjustment.clone(),
+        }
+    }
+}
+
 impl TryInto<Series> for KeyTree {
     type Error = KeyTreeError;
 
@@ -73,7 +99,7 @@ impl IntoKeyTree for Series {
     fn keytree(&self) -> KeyTreeString {
         let mut kt = KeyTreeString::new();
 
-        kt.push_key(0, "series_item");
+        kt.push_key(0, "series_meta");
         kt.push_keyvalue(1, "realtime", &self.realtime);
         kt.push_keyvalue(1, "series_id", &self.series_id.to_string());
         kt.push_keyvalue(1, "title", &self.title);
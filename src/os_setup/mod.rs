@@ -1,6 +1,7 @@
 //! Robust handling of `gecko_driver` and `shared_http` as Linux processes.
 //! 
 use anyhow::{anyhow, bail, Error, Result};
+use crate::server::{DEFAULT_BIND, DEFAULT_PORT};
 use std::{
     env,
     fmt,
@@ -220,8 +221,13 @@ pub fn shutdown_geckodriver() -> Result<()> {
 }
 
 
-/// Start `shared_http`.
+/// Start `shared_http` on the default address and port.
 pub fn start_shared_http<P: AsRef<Path>>(root_dir: P) -> Result<()> {
+    start_shared_http_on(root_dir, DEFAULT_BIND, DEFAULT_PORT)
+}
+
+/// Start `shared_http` listening on `bind:port`.
+pub fn start_shared_http_on<P: AsRef<Path>>(root_dir: P, bind: &str, port: u16) -> Result<()> {
     let root = root_dir.as_ref().to_path_buf();
     let path = full_path(&root)?;
 
@@ -231,8 +237,12 @@ pub fn start_shared_http<P: AsRef<Path>>(root_dir: P) -> Result<()> {
     };
     Command::new("shared_http")
         .arg(path)
+        .arg("--bind")
+        .arg(bind)
+        .arg("--port")
+        .arg(port.to_string())
         .spawn()?;
-    println!("shared_http running");
+    println!("shared_http running on {}:{}", bind, port);
     Ok(())
 }
 
@@ -304,7 +314,7 @@ pub mod test {
             pids_ports_cmds().unwrap()
                 .iter()
                 .find(|(_, port, cmd)| {
-                    port == &Port(8080) &&
+                    port == &Port(DEFAULT_PORT.into()) &&
                     cmd == &Cmd::from("shared_http")
                 }).is_some()
         )
@@ -317,7 +327,7 @@ pub mod test {
             pids_ports_cmds().unwrap()
                 .iter()
                 .find(|(_, port, cmd)| {
-                    port == &Port(8080) &&
+                    port == &Port(DEFAULT_PORT.into()) &&
                     cmd == &Cmd::from("shared_http")
                 }).is_none()
         )
@@ -1,71 +1,149 @@
+//! The page model of the time-series graphics. A `PageSpec` is parsed from and serialized to the
+//! `page` keys of the files in `ts_graphics/spec`, which are loaded together by
+//! [`ts_spec_from_resources`](../ts_graphics/ts_spec/fn.ts_spec_from_resources.html).
 
 use crate::{
     countries::Country,
-    file_resources::DataType,
-    primitives::SeriesId,
-    ts_graphics::ts_spec::GraphicSpec,
+    error::Result,
+    primitives::{DataType, SeriesId},
+    ts_graphics::series_data::{load_series, SeriesData, SeriesKey},
+    ts_graphics::ts_spec::{GraphicRange, GraphicSpec},
 };
 use key_tree::{KeyTree, KeyTreeError};
 use key_tree::serialize::{IntoKeyTree, KeyTreeString};
-use std::collections::BTreeMap;
-
-
-// The data-structures which hold data that are used by a server.
+use std::{
+    collections::BTreeSet,
+    path::Path,
+};
 
 // === PageSpec ===================================================================================
 
-// There are two important functions that need to be built.
-// 1. SeriesSpec.into_json(). This is done in lib.rs.
-// 2. PageJson.into_data()
-
-/// Component of time-series specification.
+/// A page of graphics, which is a component of
+/// [`TSSpec`](../ts_graphics/ts_spec/struct.TSSpec.html).
 /// ```
-/// page:
-///     country:            Australia
-///     data_type:          u
-///     index:              0
-///     graphic:
+/// # use key_tree::KeyTree;
+/// # use graphics_pipeline::page_spec::PageSpec;
+/// # let s = "
+///     page:
+///         country:        Australia
+///         data_type:      u
+///         index:          0
+///
 ///         series:
 ///             data_type:  u
 ///             series_id:  AUSURAMS
 ///         series:
 ///             data_type:  u
 ///             series_id:  AUSURANAA
+///
+///         graphic:
+///             category:   collation
+///             series_id:  AUSURAMS
+///             series_id:  AUSURANAA
+/// # ";
+/// let ps: PageSpec = KeyTree::parse_str(s)
+///     .unwrap()
+///     .try_into()
+///     .unwrap();
+/// # assert_eq!(ps.series(&"AUSURANAA".parse().unwrap()).unwrap().series_id().to_string(), "AUSURANAA");
 /// ```
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PageSpec {
-    country:    Country,
-    data_type:  DataType,
-    index:      usize,
-    height_opt: Option<f32>,
-    pub seriess:    BTreeMap<SeriesId, series_spec::SeriesSpec>,
-    pub graphics:   Vec<GraphicSpec>,
+    pub(crate) country: Country,
+    pub(crate) data_type: DataType,
+    pub(crate) index: usize,
+    pub(crate) height_opt: Option<f32>,
+    pub(crate) seriess: Vec<Series>,
+    pub(crate) graphics: Vec<GraphicSpec>,
 }
 
 impl TryInto<PageSpec> for KeyTree {
     type Error = KeyTreeError;
 
     fn try_into(self) -> std::result::Result<PageSpec, Self::Error> {
-
-        let seriess_vec: Vec<SeriesSpec> = self.vec_at("page::series")?;
-        let mut map = BTreeMap::new();
-        for series_spec in seriess_vec {
-            map.insert(series_spec.series_id.clone(), series_spec);
-        } 
-
         Ok(
             PageSpec {
                 country:    self.from_str("page::country")?, 
                 data_type:  self.from_str("page::data_type")?,
                 index:      self.from_str("page::index")?,
                 height_opt: self.opt_from_str("page::height")?,
-                seriess:    map, 
-                graphics:   self.vec_at("page::graphic")?,
+                seriess:    self.opt_vec_at("page::series")?,
+                graphics:   self.opt_vec_at("page::graphic")?,
             }
         )
     }
 }
 
+impl PageSpec {
+
+    pub fn country(&self) -> Country {
+        self.country
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn seriess(&self) -> &[Series] {
+        &self.seriess
+    }
+
+    pub fn graphics(&self) -> &[GraphicSpec] {
+        &self.graphics
+    }
+
+    /// Look up a series of the page by its id.
+    pub fn series(&self, series_id: &SeriesId) -> Option<&Series> {
+        self.seriess.iter().find(|series| &series.series_id == series_id)
+    }
+
+    /// The data type of a series on the page, or of the series it is transformed from, which
+    /// defaults to the data type of the page.
+    pub fn series_data_type(&self, series_id: &SeriesId) -> DataType {
+        self.series(series_id)
+            .or_else(|| self.series(&series_id.stem()))
+            .map_or(self.data_type, |series| series.data_type)
+    }
+
+    /// The series on the page or its graphics, keyed under their data types. Graphics may show
+    /// transformed series, such as `AUSURAMS_a`, which are not series of the page.
+    pub fn series_keys(&self) -> BTreeSet<SeriesKey> {
+        self.seriess
+            .iter()
+            .map(|series| &series.series_id)
+            .chain(self.graphics.iter().flat_map(|graphic| graphic.series_ids.iter()))
+            .map(|series_id| (self.series_data_type(series_id), self.country, series_id.clone()))
+            .collect()
+    }
+
+    /// Load the data of each series of a graphic which has data under `data_root`.
+    pub fn graphic_data<'a>(
+        &self,
+        data_root: &Path,
+        graphic: &'a GraphicSpec) -> Result<Vec<(&'a SeriesId, SeriesData)>>
+    {
+        let mut acc = Vec::new();
+        for series_id in graphic.series_ids.iter() {
+            let key = (self.series_data_type(series_id), self.country, series_id.clone());
+            if let Some(series_data) = load_series(data_root, &key)? {
+                acc.push((series_id, series_data));
+            }
+        }
+        Ok(acc)
+    }
+
+    /// Compute a range for a graphic from its data, using the policy of the page's data type.
+    pub fn auto_range(&self, data_root: &Path, graphic: &GraphicSpec) -> Result<Option<GraphicRange>> {
+        let data = self.graphic_data(data_root, graphic)?;
+        let values = data.iter().flat_map(|(_, series_data)| series_data.values.iter().copied());
+        Ok(GraphicRange::auto(self.data_type, values))
+    }
+}
+
 impl IntoKeyTree for PageSpec {
     fn keytree(&self) -> KeyTreeString {
         let mut kt = KeyTreeString::new();
@@ -73,8 +151,11 @@ impl IntoKeyTree for PageSpec {
         kt.push_keyvalue(1, "country", self.country);
         kt.push_keyvalue(1, "data_type", self.data_type);
         kt.push_keyvalue(1, "index", self.index);
+        if let Some(height) = self.height_opt {
+            kt.push_keyvalue(1, "height", height);
+        }
 
-        for (_, series) in self.seriess.iter() {
+        for series in &self.seriess {
             kt.push_keytree(1, series.keytree());
         }
 
@@ -84,3 +165,99 @@ impl IntoKeyTree for PageSpec {
         kt
     }
 }
+
+// === Series =====================================================================================
+
+/// The specification for a series, that is used across the build pipeline. The keytree representation
+/// looks like
+/// ```
+/// # use key_tree::KeyTree;
+/// # use graphics_pipeline::page_spec::Series;
+/// # let spec = r#"
+///   series:
+///       data_type:          u
+///       series_id:          LRHUTTTTAUA156S
+/// # "#;
+/// let _: Series = KeyTree::parse_str(spec)
+///     .unwrap()
+///     .try_into()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct Series {
+    pub(crate) data_type:      DataType,
+    pub(crate) series_id:      SeriesId,
+}
+
+impl Series {
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    pub fn series_id(&self) -> &SeriesId {
+        &self.series_id
+    }
+}
+
+impl TryInto<Series> for KeyTree {
+    type Error = KeyTreeError;
+
+    fn try_into(self) -> std::result::Result<Series, Self::Error> {
+        Ok(
+            Series {
+                data_type:  self.from_str("series::data_type")?, 
+                series_id:  self.from_str("series::series_id")?,
+            }
+        )
+    }
+}
+
+impl IntoKeyTree for Series {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "series");
+        kt.push_keyvalue(1, "data_type", self.data_type);
+        kt.push_keyvalue(1, "series_id", &self.series_id);
+        kt
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn page_keytree_should_round_trip() {
+        let s = r#"
+          page:
+              country:        New Zealand
+              data_type:      u
+              index:          1
+              height:         300
+
+              series:
+                  data_type:  u
+                  series_id:  LRHUTTTTNZQ156S
+
+              graphic:
+                  category:   source
+                  range:      0 to 12
+                  note:       Quarterly.
+                  series_id:  LRHUTTTTNZQ156S
+        "#;
+        let page: PageSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let output: PageSpec = KeyTree::parse_str(&page.keytree().to_string())
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(output.keytree().to_string(), page.keytree().to_string());
+        assert_eq!(output.index(), 1);
+        assert_eq!(output.height_opt, Some(300.0));
+        assert_eq!(output.graphics()[0].note.as_deref(), Some("Quarterly."));
+        assert!(output.series(&SeriesId::new("LRHUTTTTNZQ156S")).is_some());
+        assert!(output.series(&SeriesId::new("AUSURAMS")).is_none());
+    }
+}
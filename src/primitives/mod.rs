@@ -14,6 +14,18 @@ pub enum DataType {
     Inf,
 }
 
+impl DataType {
+
+    /// A description of the data type for page titles.
+    pub fn title(&self) -> &'static str {
+        match self {
+            DataType::U => "Unemployment",
+            DataType::Cpi => "Consumer Price Index",
+            DataType::Inf => "Inflation",
+        }
+    }
+}
+
 impl fmt::Display for DataType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let label = match self {
@@ -57,6 +69,11 @@ impl SeriesId {
         let inner = self.0.split('_').next().unwrap().clone();
         SeriesId(String::from(inner)) 
     }
+
+    /// Return true if the series id has a transformation modification, like `LRHUTTTTAUA156N_a`.
+    pub fn is_transformed(&self) -> bool {
+        self.0.contains('_')
+    }
 }
 
 impl FromStr for SeriesId {
@@ -1,21 +1,36 @@
-use anyhow::Result;
 use crate::{
     countries::Country,
+    error::{Error, Result},
     file_resources::IntoResources,
     file_resources::impls::Spec,
     primitives::{DataType, SeriesId},
+    spec_files::load_spec_files,
+    spec_files::diagnostics::{parses, KeyRule, SpecError, SpecErrors},
 };
 use key_tree::{KeyTree, KeyTreeError};
-use std::path::{Path, PathBuf};
+use key_tree::serialize::{IntoKeyTree, KeyTreeString};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The keys of a series specification.
+pub const SERIESS_SCHEMA: &[KeyRule] = &[
+    KeyRule::block("seriess", false),
+    KeyRule::block("seriess::series", false),
+    KeyRule::value("seriess::series::data_type", true, "data type", parses::<DataType>),
+    KeyRule::value("seriess::series::country", true, "country", parses::<Country>),
+    KeyRule::value("seriess::series::series_id", true, "series id", parses::<SeriesId>),
+];
 
+/// Return a series specification in the `specs` directory, merged with the fragments it includes.
 pub fn series_spec_from_file<P: AsRef<Path>>(data_root: P, file: P) -> Result<SeriessSpec> {
     let root: PathBuf = data_root.as_ref().to_path_buf();
     let spec_file: PathBuf = file.as_ref().to_path_buf();
 
     let spec_path = Spec.full_path(root, spec_file)?;
 
-    let spec: SeriessSpec = KeyTree::parse(spec_path)?.try_into()?;
-    Ok(spec)
+    SeriessSpec::merge(load_spec_files(&[spec_path], SERIESS_SCHEMA)?)
 }
 
 /// Return the deserialization of a series specification.
@@ -59,6 +74,42 @@ pub struct SeriessSpec {
 }
 
 impl SeriessSpec {
+
+    /// Merge the fragments of a spec, each with the file it came from. A `SeriesId` may only
+    /// appear once in each data type and country.
+    pub fn merge(fragments: Vec<(PathBuf, SeriessSpec)>) -> Result<SeriessSpec> {
+        let mut seen: HashMap<(DataType, Country, SeriesId), PathBuf> = HashMap::new();
+        let mut duplicates = Vec::new();
+        let mut series = Vec::new();
+
+        for (path, fragment) in fragments {
+            for series_spec in fragment.series {
+                let key = (series_spec.data_type, series_spec.country, series_spec.series_id.clone());
+                match seen.get(&key) {
+                    Some(first) => duplicates.push(SpecError {
+                        file:       path.clone(),
+                        line:       None,
+                        key_path:   String::new(),
+                        value:      None,
+                        message:    format!(
+                            "Series [{}] is already in '{}'",
+                            series_spec.series_id,
+                            first.display(),
+                        ),
+                    }),
+                    None => {
+                        seen.insert(key, path.clone());
+                        series.push(series_spec);
+                    },
+                }
+            }
+        }
+        if !duplicates.is_empty() {
+            return Err(Error::Spec(SpecErrors(duplicates)))
+        }
+        Ok(SeriessSpec { series })
+    }
+
     pub(crate) fn iter(&self) -> SeriessSpecIter {
         SeriessSpecIter {
             data: &self,
@@ -75,6 +126,17 @@ impl TryInto<SeriessSpec> for KeyTree {
     }
 }
 
+impl IntoKeyTree for SeriessSpec {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "seriess");
+        for series_spec in &self.series {
+            kt.push_keytree(1, series_spec.keytree());
+        }
+        kt
+    }
+}
+
 // === SeriessSpecIter ============================================================================
 
 pub struct SeriessSpecIter<'a> {
@@ -160,11 +222,24 @@ impl TryInto<SeriesSpec> for KeyTree {
     }
 }
 
+impl IntoKeyTree for SeriesSpec {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "series");
+        kt.push_keyvalue(1, "data_type", self.data_type);
+        kt.push_keyvalue(1, "country", self.country);
+        kt.push_keyvalue(1, "series_id", &self.series_id);
+        kt
+    }
+}
+
 #[cfg(test)]
 pub mod test {
 
     use key_tree::KeyTree;
+    use key_tree::serialize::IntoKeyTree;
     use crate::series_spec::SeriessSpec;
+    use std::path::PathBuf;
 
     #[test]
     fn spec_from_keytree_should_work() {
@@ -186,4 +261,39 @@ pub mod test {
         assert!(iter.next().is_some());
         assert!(iter.next().is_some());
     }
+
+    #[test]
+    fn merge_should_reject_duplicates_across_fragments() {
+        let s = r#"
+            seriess:
+                series:
+                    data_type:          u
+                    country:            Australia
+                    series_id:          AUSURAMS
+        "#;
+        let fragment = || -> SeriessSpec { KeyTree::parse_str(s).unwrap().try_into().unwrap() };
+        let fragments = vec!(
+            (PathBuf::from("australia.keytree"), fragment()),
+            (PathBuf::from("extra.keytree"), fragment()),
+        );
+        let err = SeriessSpec::merge(fragments).unwrap_err().to_string();
+        assert_eq!(err, "extra.keytree: Series [AUSURAMS] is already in 'australia.keytree'");
+    }
+
+    #[test]
+    fn keytree_should_round_trip() {
+        let s = r#"
+            seriess:
+                series:
+                    data_type:          u
+                    country:            New Zealand
+                    series_id:          LRHUTTTTNZQ156S
+        "#;
+        let spec: SeriessSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let output: SeriessSpec = KeyTree::parse_str(&spec.keytree().to_string())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(output.series, spec.series);
+    }
 }
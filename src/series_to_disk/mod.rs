@@ -1,15 +1,19 @@
-use anyhow::Result;
+use anyhow::anyhow;
 use crate::{
     countries::Country,
+    error::Result,
     file_resources::impls::{CsvRawData, Spec},
     file_resources::IntoResources,
+    fred_client::FredClient,
     primitives::{DataType, SeriesId},
-    series_spec::{SeriesSpec, SeriessSpec},
+    series_spec::{SeriesSpec, SeriessSpec, SERIESS_SCHEMA},
+    spec_files::load_spec_files,
+    workers::{map_series, Batch, DEFAULT_WORKERS},
 };
-use key_tree::KeyTree;
 use std::{
     collections::BTreeMap,
     ffi::OsStr,
+    fs,
     path::{Path, PathBuf},
 };
 
@@ -22,12 +26,40 @@ where
 {
     let root: PathBuf = root_dir.as_ref().to_path_buf();
     let spec_path = Spec.full_path(&root, ts_spec_path)?;
-    let spec: SeriessSpec = KeyTree::parse(spec_path)?.try_into()?;
+    let spec = SeriessSpec::merge(load_spec_files(&[spec_path], SERIESS_SCHEMA)?)?;
     Ok(spec.iter().collect())
 }
 
-/// Checks if raw data is synced to ts_spec and displays results.
+/// Checks if raw data is synced to ts_spec and displays results. Fails if a series could not be
+/// checked.
 pub fn verify_raw<P, S>(root_dir: P, ts_spec_path: S) -> Result<()>
+where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+{
+    let status = raw_status(root_dir, ts_spec_path, DEFAULT_WORKERS)?;
+    for (_series_id, (filename, found)) in status.done.iter() {
+        match found {
+            true => println!(" ok  {}", filename.display()),
+            false => println!("none {}", filename.display()),
+        }
+    }
+    for failure in status.failures() {
+        println!("fail {}", failure);
+    }
+    if !status.is_ok() {
+        return Err(anyhow!("Failed to check {} series", status.failed.len()).into())
+    }
+    Ok(())
+}
+
+/// Return the CSV file name of each series in the spec, and whether it is in `raw_data`, checking
+/// up to `workers` buckets at once. Fails if the `raw_data` directory of a bucket is missing or
+/// holds files other than `.csv` and `.meta` files.
+pub fn raw_status<P, S>(
+    root_dir: P,
+    ts_spec_path: S,
+    workers: usize) -> Result<Batch<SeriesId, (PathBuf, bool)>>
 where
         P: AsRef<Path>,
         S: AsRef<OsStr>,
@@ -36,23 +68,64 @@ where
     let path: &OsStr = ts_spec_path.as_ref();
 
     let spec_map: SeriesSpecMap = spec_map_from_spec(&root, path)?;
-    for ((data_type, country), inner_map) in spec_map.map.iter() {
-
-        let csv_raw_data = CsvRawData {country: *country, data_type: *data_type};
-
-        csv_raw_data.into_resources(&root)?;
+    for (data_type, country) in spec_map.map.keys() {
+        CsvRawData { country: *country, data_type: *data_type }.into_resources(&root)?;
+    }
+    Ok(map_series(&spec_map, workers, |series_spec| {
+        let csv_raw_data = CsvRawData {
+            country:    series_spec.country(),
+            data_type:  series_spec.data_type(),
+        };
+        let filename = PathBuf::from(&series_spec.series_id().to_string()).with_extension("csv");
+        let found = csv_raw_data.has_file(&root, &filename)?;
+        Ok((filename, found))
+    }))
+}
 
-        for (_series_id, series_spec) in inner_map.iter() {
+/// Download the observations of each series in the spec from FRED into
+/// `raw_data/<data_type>/<country>/<series_id>.csv`, up to `workers` buckets at once, returning
+/// the file written for each series.
+pub fn fetch_raw<P, S>(
+    root_dir: P,
+    ts_spec_path: S,
+    client: &FredClient,
+    workers: usize) -> Result<Batch<SeriesId, PathBuf>>
+where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+{
+    let root: PathBuf = root_dir.as_ref().to_path_buf();
+    let spec_map: SeriesSpecMap = spec_map_from_spec(&root, ts_spec_path)?;
+    Ok(map_series(&spec_map, workers, |series_spec| fetch_series(&root, series_spec, client)))
+}
 
-            let filename = PathBuf::from(&series_spec.series_id().to_string()).with_extension("csv");
+/// Download the observations of one series from FRED into `raw_data`, returning the file written.
+pub fn fetch_series(
+    root: &Path,
+    series_spec: &SeriesSpec,
+    client: &FredClient) -> Result<PathBuf>
+{
+    let observations = client.observations(&series_spec.series_id().to_string())?;
+    let path = series_dir(root, "raw_data", series_spec)?
+        .join(series_spec.series_id().to_string())
+        .with_extension("csv");
+    fs::write(&path, observations.to_csv())?;
+    Ok(path)
+}
 
-            match csv_raw_data.has_file(&root, &filename)? {
-                true => println!(" ok  {}", filename.display()),
-                false => println!("none {}", filename.display()),
-            }
-        }
-    }
-    Ok(())
+/// The directory of a series under `dir` in the data root, like `raw_data/u/australia`, which is
+/// created if it does not exist.
+pub fn series_dir(
+    root: &Path,
+    dir: &str,
+    series_spec: &SeriesSpec) -> Result<PathBuf>
+{
+    let path = root
+        .join(dir)
+        .join(series_spec.data_type().to_string())
+        .join(series_spec.country().as_filepath());
+    fs::create_dir_all(&path)?;
+    Ok(path)
 }
 
 // === SeriesSpecMap ==============================================================================
@@ -110,9 +183,24 @@ impl SeriesSpecMap {
                 let mut value = BTreeMap::new();
                 value.insert(series_spec.series_id(), (*series_spec).clone());
                 self.map.insert(key, value);
-                self.reverse.insert(series_spec.series_id(), key);
             },
         }
+        self.reverse.insert(series_spec.series_id(), key);
+    }
+
+    /// Returns true if the `SeriesId` is in the specification.
+    pub fn contains(&self, series_id: &SeriesId) -> bool {
+        self.reverse.contains_key(series_id)
+    }
+
+    /// Iterate over each `(DataType, Country)` bucket and its series, in order.
+    pub fn buckets(&self) -> impl Iterator<Item = (&(DataType, Country), &BTreeMap<SeriesId, SeriesSpec>)> {
+        self.map.iter()
+    }
+
+    /// Iterate over all `SeriesSpec`s ordered by `(DataType, Country)` and then by `SeriesId`.
+    pub fn iter(&self) -> impl Iterator<Item = &SeriesSpec> {
+        self.map.values().flat_map(|inner_map| inner_map.values())
     }
 }
 
@@ -183,6 +271,18 @@ pub mod test {
         assert_eq!(bt.get(&series_id2).unwrap(), &input2);
     }
 
+    #[test]
+    fn second_insert_with_same_key_should_be_found_by_series_id() {
+        let series_id = SeriesId::new("first");
+        let series_id2 = SeriesId::new("second");
+
+        let mut map = SeriesSpecMap::new();
+        map.insert(&SeriesSpec::new(DataType::U, Country::Australia, series_id.clone()));
+        map.insert(&SeriesSpec::new(DataType::U, Country::Australia, series_id2.clone()));
+        assert!(map.contains(&series_id));
+        assert!(map.get_series_spec(&series_id2).is_some());
+    }
+
     #[test]
     fn inserts_should_be_ordered() {
         let series_id = SeriesId::new("first");
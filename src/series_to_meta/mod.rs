@@ -1,8 +1,55 @@
+//! Use the series specification to download the metadata of each series from FRED into `.meta`
+//! files beside its raw data.
+
 use crate::{
-    series_spec,
+    error::{Error, Result},
+    fred_client::FredClient,
+    meta_data,
+    primitives::SeriesId,
+    series_spec::SeriesSpec,
+    series_to_disk::{series_dir, spec_map_from_spec},
+    workers::{map_series, Batch},
+};
+use key_tree::serialize::IntoKeyTree;
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
 };
 
-pub fn overwrite_meta_files(_series_spec: series_spec::SeriesSpec, _contents_dir: &str) {
-    unimplemented!()
+/// Write the FRED metadata of each series in the spec to
+/// `raw_data/<data_type>/<country>/<series_id>.meta`, overwriting any earlier file, up to
+/// `workers` buckets at once. Returns the file written for each series.
+pub fn overwrite_meta_files<P, S>(
+    root_dir: P,
+    series_spec_path: S,
+    client: &FredClient,
+    workers: usize) -> Result<Batch<SeriesId, PathBuf>>
+where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+{
+    let root: PathBuf = root_dir.as_ref().to_path_buf();
+    let spec_map = spec_map_from_spec(&root, series_spec_path)?;
+    Ok(map_series(&spec_map, workers, |series_spec| write_meta_file(&root, series_spec, client)))
 }
 
+/// Write the FRED metadata of one series to its `.meta` file, returning the file written.
+pub fn write_meta_file(
+    root: &Path,
+    series_spec: &SeriesSpec,
+    client: &FredClient) -> Result<PathBuf>
+{
+    let series_id = series_spec.series_id().to_string();
+    let seriess = client.series(&series_id)?;
+    let item = seriess.seriess
+        .first()
+        .ok_or_else(|| Error::DataSource(format!("FRED has no series [{}]", series_id)))?;
+    let meta = meta_data::Series::from(item);
+
+    let path = series_dir(root, "raw_data", series_spec)?
+        .join(&series_id)
+        .with_extension("meta");
+    fs::write(&path, meta.keytree().to_string())?;
+    Ok(path)
+}
@@ -0,0 +1,343 @@
+//! An actix-web server for time-series pages, built on the `HttpState` stores.
+//!
+//! | Route                 | Store                              |
+//! |-----------------------|------------------------------------|
+//! | `/js/{file}`          | `JsScripts` from `ts_graphics/js`  |
+//! | `/css/{file}`         | `TSCss`                            |
+//! | `/pid/js/{file}`      | `PidGraphicsJs`                    |
+//! | `/pid/css/{file}`     | `PidGraphicCss`                    |
+//! | `/favicon.png`        | `PidGraphicsFavIcon`               |
+//! | `/data/{data_type}/{country}/{series_id}.json` | `SeriesDataStore` |
+//! | `/{data_type}/{country}/{index}.html` | `Pages`, rendered from `ts_graphics/spec` |
+//!
+//! A path which names no file, like `/data/u/australia/AUSURAMS.csv`, is answered with the status
+//! of its [`Error`](../error/enum.Error.html).
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use crate::{
+    config::ConfigLayer,
+    countries::Country,
+    error::{Error, Result},
+    file_resources::impls::{PidGraphicCss, PidGraphicsFavIcon, PidGraphicsJs, TSCss},
+    http_state::HttpState,
+    http_state::asset_store::AssetStore,
+    http_state::hot_reload::MaybeReload,
+    primitives::{DataType, SeriesId},
+    ts_graphics::html::Pages,
+    ts_graphics::js_scripts::{JsScripts, Key},
+    ts_graphics::series_data::SeriesDataStore,
+};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+pub const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_SERIES_SPEC: &str = "series_spec.keytree";
+const SERVER_USAGE: &str = "Usage: shared_http [data_root] [--config <file>] [--bind <address>] \
+    [--port <port>] [--series-spec <file>] [--watch]";
+
+// === ServerConfig ===============================================================================
+
+/// Where to find the data root and where to listen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    pub data_root:  PathBuf,
+    pub bind:       String,
+    pub port:       u16,
+
+    /// The series specification in the `specs` directory, which lists the series to serve.
+    pub series_spec: String,
+
+    /// Reload stores when their files change.
+    pub watch:      bool,
+}
+
+impl ServerConfig {
+
+    /// Listen on the default address and port.
+    pub fn new<P: AsRef<Path>>(data_root: P) -> Self {
+        ServerConfig {
+            data_root:  data_root.as_ref().to_path_buf(),
+            bind:       DEFAULT_BIND.to_string(),
+            port:       DEFAULT_PORT,
+            series_spec: DEFAULT_SERIES_SPEC.to_string(),
+            watch:      false,
+        }
+    }
+}
+
+// === ServerArgs =================================================================================
+
+/// The command-line arguments of `shared_http`, which override the
+/// [`PipelineConfig`](../config/struct.PipelineConfig.html).
+/// ```
+/// # use graphics_pipeline::server::ServerArgs;
+/// let args = vec!("../../shared_data", "--port", "8081", "--watch");
+/// let args = ServerArgs::from_args(args.into_iter().map(String::from)).unwrap();
+/// assert_eq!(args.layer.port, Some(8081));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerArgs {
+
+    /// The config file given by `--config`.
+    pub config:     Option<PathBuf>,
+    pub layer:      ConfigLayer,
+    pub series_spec: String,
+    pub watch:      bool,
+}
+
+impl ServerArgs {
+
+    /// Read command-line arguments, excluding the program name.
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self> {
+        let mut layer = ConfigLayer::default();
+        let mut config = None;
+        let mut series_spec = DEFAULT_SERIES_SPEC.to_string();
+        let mut watch = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    let file = next_value(&mut args, "--config requires a file")?;
+                    config = Some(PathBuf::from(file));
+                },
+                "--bind" => {
+                    layer.bind = Some(next_value(&mut args, "--bind requires an address")?);
+                },
+                "--port" => {
+                    let s = next_value(&mut args, "--port requires a port")?;
+                    let port = s
+                        .parse()
+                        .map_err(|_| Error::Config(format!("Failed to parse port [{}]", s)))?;
+                    layer.port = Some(port);
+                },
+                "--series-spec" => {
+                    series_spec = next_value(&mut args, "--series-spec requires a file")?;
+                },
+                "--watch" => watch = true,
+                _ if arg.starts_with("--") => {
+                    let message = format!("Unknown option [{}]\n{}", arg, SERVER_USAGE);
+                    return Err(Error::Config(message))
+                },
+                _ => {
+                    if layer.data_root.is_some() {
+                        return Err(Error::Config(format!("Unexpected argument [{}]", arg)))
+                    }
+                    layer.data_root = Some(PathBuf::from(arg));
+                },
+            }
+        }
+
+        Ok(ServerArgs { config, layer, series_spec, watch })
+    }
+}
+
+// The value of an option, or a configuration error saying what the option requires.
+fn next_value<I: Iterator<Item = String>>(args: &mut I, message: &str) -> Result<String> {
+    args.next().ok_or_else(|| Error::Config(message.to_string()))
+}
+
+// === AppState ===================================================================================
+
+/// All the stores served by the server.
+pub struct AppState {
+    ts_js:      MaybeReload<JsScripts>,
+    ts_css:     MaybeReload<AssetStore<TSCss>>,
+    pid_js:     MaybeReload<AssetStore<PidGraphicsJs>>,
+    pid_css:    MaybeReload<AssetStore<PidGraphicCss>>,
+    favicon:    MaybeReload<AssetStore<PidGraphicsFavIcon>>,
+    data:       MaybeReload<SeriesDataStore>,
+    pages:      MaybeReload<Pages>,
+}
+
+impl AppState {
+    pub fn new(config: &ServerConfig) -> Result<Self> {
+        let root = &config.data_root;
+        let watch = config.watch;
+        Ok(
+            AppState {
+                ts_js:      MaybeReload::new(JsScripts::new(root)?, root, watch)?,
+                ts_css:     MaybeReload::new(AssetStore::new(TSCss, root)?, root, watch)?,
+                pid_js:     MaybeReload::new(AssetStore::new(PidGraphicsJs, root)?, root, watch)?,
+                pid_css:    MaybeReload::new(AssetStore::new(PidGraphicCss, root)?, root, watch)?,
+                favicon:    MaybeReload::new(AssetStore::new(PidGraphicsFavIcon, root)?, root, watch)?,
+                data:       MaybeReload::new(SeriesDataStore::new(root, &config.series_spec)?, root, watch)?,
+                pages:      MaybeReload::new(Pages::new(root)?, root, watch)?,
+            }
+        )
+    }
+}
+
+// === Server =====================================================================================
+
+/// Load all stores and serve them until the server is shut down.
+pub async fn run(config: ServerConfig) -> Result<()> {
+    let state = web::Data::new(AppState::new(&config)?);
+
+    println!("shared_http listening on {}:{}", config.bind, config.port);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .configure(routes)
+    })
+    .bind((config.bind.as_str(), config.port))?
+    .run()
+    .await?;
+
+    Ok(())
+}
+
+/// Mount all routes. `AppState` must be added as app data.
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .route("/js/{file}", web::get().to(ts_js))
+        .route("/css/{file}", web::get().to(ts_css))
+        .route("/pid/js/{file}", web::get().to(pid_js))
+        .route("/pid/css/{file}", web::get().to(pid_css))
+        .route("/favicon.png", web::get().to(favicon))
+        .route("/data/{data_type}/{country}/{file}", web::get().to(data))
+        .route("/{data_type}/{country}/{file}", web::get().to(page));
+}
+
+async fn ts_js(state: web::Data<AppState>, file: web::Path<String>, req: HttpRequest) -> Result<HttpResponse> {
+    let key = Key::from_path(Path::new(file.as_str())).map_err(|_| no_file("js", &file))?;
+    Ok(state.ts_js.respond(key, &req))
+}
+
+async fn ts_css(state: web::Data<AppState>, file: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    state.ts_css.respond(file.into_inner(), &req)
+}
+
+async fn pid_js(state: web::Data<AppState>, file: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    state.pid_js.respond(file.into_inner(), &req)
+}
+
+async fn pid_css(state: web::Data<AppState>, file: web::Path<String>, req: HttpRequest) -> HttpResponse {
+    state.pid_css.respond(file.into_inner(), &req)
+}
+
+async fn favicon(state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    state.favicon.respond("favicon.png".to_string(), &req)
+}
+
+async fn data(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest) -> Result<HttpResponse>
+{
+    let (data_type, country, file) = path.into_inner();
+    let key = series_key(&data_type, &country, &file)
+        .ok_or_else(|| no_file(&format!("data/{}/{}", data_type, country), &file))?;
+    Ok(state.data.respond(key, &req))
+}
+
+async fn page(
+    state: web::Data<AppState>,
+    path: web::Path<(String, String, String)>,
+    req: HttpRequest) -> Result<HttpResponse>
+{
+    let (data_type, country, file) = path.into_inner();
+    let key = page_key(&data_type, &country, &file)
+        .ok_or_else(|| no_file(&format!("{}/{}", data_type, country), &file))?;
+    Ok(state.pages.respond(key, &req))
+}
+
+// The error for a path which names no file, which is answered with `404 Not Found`.
+fn no_file(dir: &str, file: &str) -> Error {
+    Error::FileNotFound { file: PathBuf::from(file), dir: PathBuf::from(dir) }
+}
+
+// Read a key from a path like `/u/australia/0.html`.
+fn page_key(data_type: &str, country: &str, file: &str) -> Option<(DataType, Country, usize)> {
+    let index = file.strip_suffix(".html")?;
+    Some((data_type.parse().ok()?, country.parse().ok()?, index.parse().ok()?))
+}
+
+// Read a key from a path like `/data/u/australia/AUSURAMS.json`.
+fn series_key(data_type: &str, country: &str, file: &str) -> Option<(DataType, Country, SeriesId)> {
+    let series_id = file.strip_suffix(".json")?;
+    Some((data_type.parse().ok()?, country.parse().ok()?, SeriesId::new(series_id)))
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::config::PipelineConfig;
+
+    fn args(v: Vec<&str>) -> impl Iterator<Item = String> + '_ {
+        v.into_iter().map(String::from)
+    }
+
+    // Load the server config from `args` and the environment `env`.
+    fn load<F: Fn(&str) -> Option<String>>(v: Vec<&str>, env: F) -> Result<ServerConfig> {
+        let args = ServerArgs::from_args(args(v))?;
+        let config = PipelineConfig::load_with(args.config.as_deref(), args.layer, env)?;
+        Ok(config.server_config(&args.series_spec, args.watch))
+    }
+
+    fn no_env(_key: &str) -> Option<String> {
+        None
+    }
+
+    fn data_root() -> String {
+        std::env::temp_dir().to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn config_should_default_to_port_8080() {
+        let config = load(vec!(&data_root()), no_env).unwrap();
+        assert_eq!(config, ServerConfig::new(std::env::temp_dir().canonicalize().unwrap()));
+    }
+
+    #[test]
+    fn config_should_read_bind_and_port() {
+        let root = data_root();
+        let config = load(vec!("--bind", "0.0.0.0", &root, "--port", "9000"), no_env).unwrap();
+        assert_eq!(config.bind, "0.0.0.0");
+        assert_eq!(config.port, 9000);
+    }
+
+    #[test]
+    fn arguments_should_override_the_environment() {
+        let env = |key: &str| match key {
+            "GP_DATA_ROOT" => Some(data_root()),
+            "GP_BIND" => Some("0.0.0.0".to_string()),
+            "GP_PORT" => Some("9000".to_string()),
+            _ => None,
+        };
+        let config = load(vec!("--watch"), env).unwrap();
+        assert_eq!((config.bind.as_str(), config.port, config.watch), ("0.0.0.0", 9000, true));
+
+        let config = load(vec!("--port", "9001"), env).unwrap();
+        assert_eq!(config.port, 9001);
+    }
+
+    #[test]
+    fn series_key_should_read_filepath_country() {
+        assert_eq!(
+            series_key("u", "new_zealand", "LRHUTTTTNZQ156S.json"),
+            Some((DataType::U, Country::NewZealand, SeriesId::new("LRHUTTTTNZQ156S"))),
+        );
+        assert_eq!(series_key("u", "new_zealand", "LRHUTTTTNZQ156S.csv"), None);
+    }
+
+    #[test]
+    fn page_key_should_read_index() {
+        assert_eq!(page_key("cpi", "japan", "3.html"), Some((DataType::Cpi, Country::Japan, 3)));
+        assert_eq!(page_key("cpi", "japan", "three.html"), None);
+    }
+
+    #[test]
+    fn config_should_fail_without_data_root() {
+        assert!(load(vec!("--watch"), no_env).is_err());
+        assert!(ServerArgs::from_args(args(vec!("--port"))).is_err());
+    }
+
+    #[test]
+    fn paths_which_name_no_file_should_be_not_found() {
+        let e = no_file("data/u/australia", "AUSURAMS.csv");
+        assert_eq!(e.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+}
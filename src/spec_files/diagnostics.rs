@@ -0,0 +1,231 @@
+//! Check the source of a spec against a schema of its keys, so that every mistake in a spec is
+//! reported with its file, line, key path and value, rather than only the first.
+//! ```text
+//! specs/series_spec.keytree:12: seriess::series::data_type: Failed to parse a data type [unemp]
+//! specs/series_spec.keytree:14: seriess::series::tags: Unknown key [unemployment]
+//! specs/series_spec.keytree:10: seriess::series::country: Missing key
+//! ```
+
+use crate::spec_files::INCLUDE_KEY;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+// === SpecError ==================================================================================
+
+/// A mistake in a spec file. The line is `None` for mistakes found after parsing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecError {
+    pub file:       PathBuf,
+    pub line:       Option<usize>,
+    pub key_path:   String,
+    pub value:      Option<String>,
+    pub message:    String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if !self.key_path.is_empty() {
+            write!(f, ": {}", self.key_path)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(value) = &self.value {
+            write!(f, " [{}]", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every mistake found while loading a spec.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecErrors(pub Vec<SpecError>);
+
+impl fmt::Display for SpecErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|e| e.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for SpecErrors {}
+
+// === KeyRule ====================================================================================
+
+/// A key allowed in a spec, by its path from the root key such as `page::graphic::series_id`.
+#[derive(Clone, Copy)]
+pub struct KeyRule {
+    pub path:       &'static str,
+    pub required:   bool,
+
+    /// What the value must parse as, and a check that it does. `None` for keys which open a
+    /// block.
+    pub value:      Option<(&'static str, fn(&str) -> bool)>,
+}
+
+impl KeyRule {
+    pub const fn block(path: &'static str, required: bool) -> Self {
+        KeyRule { path, required, value: None }
+    }
+
+    pub const fn value(
+        path: &'static str,
+        required: bool,
+        kind: &'static str,
+        check: fn(&str) -> bool) -> Self
+    {
+        KeyRule { path, required, value: Some((kind, check)) }
+    }
+}
+
+/// A check that a value parses as `T`, for `KeyRule::value`.
+pub fn parses<T: FromStr>(s: &str) -> bool {
+    s.parse::<T>().is_ok()
+}
+
+// === Checking ===================================================================================
+
+// A block key whose children are being read.
+struct Open {
+    indent:     usize,
+    path:       String,
+    line:       usize,
+    known:      bool,
+    children:   Vec<String>,
+}
+
+/// Check every line of a spec against the rules, returning every mistake found.
+pub fn check_source(file: &Path, source: &str, schema: &[KeyRule]) -> Vec<SpecError> {
+    let error = |line: Option<usize>, key_path: &str, value: Option<&str>, message: &str| SpecError {
+        file:       file.to_path_buf(),
+        line,
+        key_path:   key_path.to_string(),
+        value:      value.map(String::from),
+        message:    message.to_string(),
+    };
+
+    let mut acc = Vec::new();
+    let mut stack: Vec<Open> = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let n = i + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("//") { continue }
+
+        let indent = line.len() - line.trim_start().len();
+        while stack.last().map_or(false, |open| open.indent >= indent) {
+            let open = stack.pop().unwrap();
+            acc.extend(check_required(&open, schema, &error));
+        }
+
+        let (key, value) = match trimmed.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                acc.push(error(Some(n), "", Some(trimmed), "Expected 'key:' or 'key: value'"));
+                continue
+            },
+        };
+        let parent_known = stack.last().map_or(true, |open| open.known);
+        let path = match stack.last_mut() {
+            Some(open) => {
+                open.children.push(key.to_string());
+                format!("{}::{}", open.path, key)
+            },
+            None => key.to_string(),
+        };
+        if key == INCLUDE_KEY { continue }
+
+        let rule = schema.iter().find(|rule| rule.path == path);
+        let value_opt = if value.is_empty() { None } else { Some(value) };
+
+        match (rule.and_then(|rule| rule.value), value_opt) {
+            (_, None) if rule.map_or(true, |rule| rule.value.is_none()) => {
+                if rule.is_none() && parent_known {
+                    acc.push(error(Some(n), &path, None, "Unknown key"));
+                }
+                stack.push(Open { indent, path, line: n, known: rule.is_some(), children: Vec::new() });
+            },
+            _ if !parent_known => {},
+            (_, Some(value)) if rule.is_none() => {
+                acc.push(error(Some(n), &path, Some(value), "Unknown key"));
+            },
+            (Some(_), None) => acc.push(error(Some(n), &path, None, "Missing value")),
+            (None, Some(value)) => acc.push(error(Some(n), &path, Some(value), "Expected a block, not a value")),
+            (Some((kind, check)), Some(value)) => {
+                if !check(value) {
+                    acc.push(error(Some(n), &path, Some(value), &format!("Failed to parse a {}", kind)));
+                }
+            },
+            (None, None) => {},
+        }
+    }
+    while let Some(open) = stack.pop() {
+        acc.extend(check_required(&open, schema, &error));
+    }
+    acc
+}
+
+// Report the required keys missing from a block, at the line of the block.
+fn check_required<F>(open: &Open, schema: &[KeyRule], error: &F) -> Vec<SpecError>
+where
+    F: Fn(Option<usize>, &str, Option<&str>, &str) -> SpecError,
+{
+    if !open.known {
+        return Vec::new()
+    }
+    schema
+        .iter()
+        .filter(|rule| rule.required)
+        .filter_map(|rule| {
+            let (parent, key) = rule.path.rsplit_once("::")?;
+            match parent == open.path && !open.children.iter().any(|child| child == key) {
+                true => Some(error(Some(open.line), rule.path, None, "Missing key")),
+                false => None,
+            }
+        })
+        .collect()
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::primitives::DataType;
+
+    const SCHEMA: &[KeyRule] = &[
+        KeyRule::block("seriess", false),
+        KeyRule::block("seriess::series", false),
+        KeyRule::value("seriess::series::data_type", true, "data type", parses::<DataType>),
+        KeyRule::value("seriess::series::series_id", true, "series id", parses::<String>),
+    ];
+
+    #[test]
+    fn every_mistake_should_be_reported_with_its_line() {
+        let s = "seriess:
+    series:
+        data_type: unemp
+        tags: x
+    series:
+        data_type: u
+        series_id: AUSURAMS
+";
+        let errors: Vec<String> = check_source(Path::new("spec.keytree"), s, SCHEMA)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!(
+                "spec.keytree:3: seriess::series::data_type: Failed to parse a data type [unemp]",
+                "spec.keytree:4: seriess::series::tags: Unknown key [x]",
+                "spec.keytree:2: seriess::series::series_id: Missing key",
+            ),
+        );
+    }
+}
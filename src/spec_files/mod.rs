@@ -0,0 +1,207 @@
+//! Compose a spec from several keytree files.
+//!
+//! Any line of a spec of the form `include: <path>` pulls in another file, relative to the
+//! directory of the including file. If the path is a directory, every `.keytree` file in it is
+//! included in name order, so a series specification can be split into per-country fragments
+//! ```text
+//! specs/
+//!     series_spec.keytree                 seriess:
+//!                                             include:    series
+//!     series/
+//!         australia.keytree               seriess:
+//!                                             series:
+//!                                                 ..
+//!         new_zealand.keytree
+//! ```
+//! Each fragment is a complete document with the same root key, and the fragments are merged by
+//! the loader of the spec. A file is read once however often it is included, and includes may
+//! nest.
+
+/// Report every mistake in a spec with its file, line and key path.
+pub mod diagnostics;
+
+use crate::{
+    error::{Error, Result},
+    spec_files::diagnostics::{check_source, KeyRule, SpecError, SpecErrors},
+};
+use key_tree::{KeyTree, KeyTreeError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The key of the include directive.
+pub const INCLUDE_KEY: &str = "include";
+
+/// A file of a spec, with its include lines removed.
+#[derive(Clone, Debug)]
+pub struct SpecFile {
+    pub path:   PathBuf,
+    pub source: String,
+}
+
+impl SpecFile {
+
+    /// A mistake in the file, which is not on a particular line.
+    pub fn error(&self, message: String) -> SpecError {
+        SpecError {
+            file:       self.path.clone(),
+            line:       None,
+            key_path:   String::new(),
+            value:      None,
+            message,
+        }
+    }
+}
+
+/// Return the value of a line like `include: series`, if it is an include line.
+/// ```
+/// # use graphics_pipeline::spec_files::include_path;
+/// assert_eq!(include_path("    include:    series/australia.keytree"), Some("series/australia.keytree"));
+/// assert_eq!(include_path("    series_id:  AUSURAMS"), None);
+/// ```
+pub fn include_path(line: &str) -> Option<&str> {
+    let (key, value) = line.trim().split_once(':')?;
+    match key.trim() == INCLUDE_KEY && !value.trim().is_empty() {
+        true => Some(value.trim()),
+        false => None,
+    }
+}
+
+/// Read the files at `paths` and every file they include, in order.
+pub fn read_spec_files(paths: &[PathBuf]) -> Result<Vec<SpecFile>> {
+    let mut acc = Vec::new();
+    let mut stack = Vec::new();
+    for path in paths {
+        read_recursive(path, &mut stack, &mut acc)?;
+    }
+    Ok(acc)
+}
+
+// Read a file or the keytree files in a directory, followed by the files they include. `stack`
+// holds the files being read, to detect cycles.
+fn read_recursive(path: &Path, stack: &mut Vec<PathBuf>, acc: &mut Vec<SpecFile>) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .map_err(|_| Error::FileNotFound {
+            file:   path.file_name().map(PathBuf::from).unwrap_or_default(),
+            dir:    path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        })?;
+
+    if path.is_dir() {
+        let mut files = Vec::new();
+        for res_entry in fs::read_dir(&path)? {
+            let file = res_entry?.path();
+            if file.is_file() && file.extension() == Some("keytree".as_ref()) {
+                files.push(file);
+            }
+        }
+        files.sort();
+        for file in files {
+            read_recursive(&file, stack, acc)?;
+        }
+        return Ok(())
+    }
+
+    if stack.contains(&path) {
+        let error = SpecError {
+            file:       path.clone(),
+            line:       None,
+            key_path:   INCLUDE_KEY.to_string(),
+            value:      None,
+            message:    "File includes itself".to_string(),
+        };
+        return Err(SpecErrors(vec!(error)).into())
+    }
+    if acc.iter().any(|spec_file| spec_file.path == path) {
+        return Ok(())
+    }
+
+    let s = fs::read_to_string(&path)?;
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    // Blank the include lines rather than dropping them, to keep line numbers.
+    let mut includes = Vec::new();
+    let source: Vec<&str> = s
+        .lines()
+        .map(|line| match include_path(line) {
+            Some(include) => {
+                includes.push(dir.join(include));
+                ""
+            },
+            None => line,
+        })
+        .collect();
+
+    acc.push(SpecFile { path: path.clone(), source: source.join("\n") });
+
+    stack.push(path);
+    for include in includes {
+        read_recursive(&include, stack, acc)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+/// Read and parse the files at `paths` and every file they include, returning each document
+/// with its file. Every file is checked against `schema`, and if there are any mistakes the
+/// error is a [`SpecErrors`](diagnostics/struct.SpecErrors.html) with all of them.
+pub fn load_spec_files<T>(paths: &[PathBuf], schema: &[KeyRule]) -> Result<Vec<(PathBuf, T)>>
+where
+    KeyTree: TryInto<T, Error = KeyTreeError>,
+{
+    let mut acc = Vec::new();
+    let mut errors = Vec::new();
+
+    for spec_file in read_spec_files(paths)? {
+        let file_errors = check_source(&spec_file.path, &spec_file.source, schema);
+        if !file_errors.is_empty() {
+            errors.extend(file_errors);
+            continue
+        }
+
+        let spec: std::result::Result<T, String> = KeyTree::parse_str(&spec_file.source)
+            .map_err(|e| e.to_string())
+            .and_then(|keytree| keytree.try_into().map_err(|e: KeyTreeError| e.to_string()));
+        match spec {
+            Ok(spec) => acc.push((spec_file.path, spec)),
+            Err(message) => errors.push(spec_file.error(message)),
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok(acc),
+        false => Err(Error::Spec(SpecErrors(errors))),
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn includes_should_be_read_once_in_order() {
+        let dir = std::env::temp_dir().join("graphics_pipeline_spec_files");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("series")).unwrap();
+
+        fs::write(dir.join("main.keytree"), "seriess:\n    include: series\n    include: series/b.keytree\n").unwrap();
+        fs::write(dir.join("series/a.keytree"), "seriess:\n").unwrap();
+        fs::write(dir.join("series/b.keytree"), "seriess:\n    include: a.keytree\n").unwrap();
+
+        let files = read_spec_files(&[dir.join("main.keytree")]).unwrap();
+        let names: Vec<&str> = files
+            .iter()
+            .map(|spec_file| spec_file.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!("main.keytree", "a.keytree", "b.keytree"));
+        assert_eq!(files[0].source.lines().nth(1), Some(""));
+
+        fs::write(dir.join("series/a.keytree"), "seriess:\n    include: ../main.keytree\n").unwrap();
+        assert!(matches!(read_spec_files(&[dir.join("main.keytree")]), Err(Error::Spec(_))));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,508 @@
+//! Render a [`PageSpec`](../ts_spec/struct.PageSpec.html) into an HTML page, using the
+//! `page.html` template from `ts_graphics/templates`. See
+//! [`template`](../template/index.html) for the template syntax.
+//!
+//! The template context has
+//!
+//! | Variable            | Value                                                          |
+//! |---------------------|----------------------------------------------------------------|
+//! | `title`             | The country and data type, such as `Australia Unemployment`    |
+//! | `country`           | The country, with `country_path` as used in file paths         |
+//! | `data_type`         | The data type, with `data_type_title`                          |
+//! | `index`             | The page index                                                 |
+//! | `root`              | The URL prefix of the site, for linking CSS and the favicon    |
+//! | `height`            | The graphic height in pixels, if the page sets one             |
+//! | `og_image`          | The URL of the page's Open Graph image, if it has one          |
+//! | `graphics`          | A list of graphics, each with `id`, `category`, `range`, `note`, `series`, `svg` and `html`, a ready-made container |
+//! | `seriess`           | A list of the page's series, each with `series_id`, `data_type` and `src` |
+//! | `scripts`           | A list of scripts in `ts_graphics/js`, each with `name` and `src` |
+//! | `data`              | If series data is embedded, a list with `series_id` and `json` for every series on the page or its graphics |
+
+use anyhow::anyhow;
+use crate::{
+    countries::Country,
+    error::Result,
+    file_resources::IntoResources,
+    file_resources::impls::{TSGraphicsJs, TSHtmlTemplate, TSPageSpec},
+    http_state::{HttpRequest, HttpResponse, HttpState},
+    http_state::encoded::EncodedBody,
+    http_state::hot_reload::Reload,
+    primitives::{DataType, SeriesId},
+    ts_graphics::js_scripts::Key,
+    ts_graphics::series_data::{SeriesData, SeriesDataStore},
+    ts_graphics::svg::{render_svg, SvgOptions},
+    ts_graphics::template::{Context, Templates, Value},
+    ts_graphics::ts_spec::{ts_spec_from_resources, GraphicRange, GraphicSpec, PageSpec},
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// The template in `ts_graphics/templates` used for time-series pages.
+pub const PAGE_TEMPLATE: &str = "page.html";
+
+/// Identifies a page.
+pub type PageKey = (DataType, Country, usize);
+
+/// The path of a page relative to the site root, such as `u/australia/0.html`.
+pub fn page_path(data_type: DataType, country: Country, index: usize) -> String {
+    format!("{}/{}/{}.html", data_type, country.as_filepath(), index)
+}
+
+/// The path of the Open Graph image of a page relative to the site root, such as
+/// `u/australia/0.png`.
+pub fn page_image_path(data_type: DataType, country: Country, index: usize) -> String {
+    format!("{}/{}/{}.png", data_type, country.as_filepath(), index)
+}
+
+/// The path of series data relative to the site root, such as `data/u/australia/AUSURAMS.json`.
+pub fn series_data_path(data_type: DataType, country: Country, series_id: &SeriesId) -> String {
+    format!("data/{}/{}/{}.json", data_type, country.as_filepath(), series_id)
+}
+
+/// Escape text for use in HTML content or attribute values.
+/// ```
+/// # use graphics_pipeline::ts_graphics::html::escape;
+/// assert_eq!(escape("<b>\"R&D\"</b>"), "&lt;b&gt;&quot;R&amp;D&quot;&lt;/b&gt;");
+/// ```
+pub fn escape(s: &str) -> String {
+    let mut acc = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => acc.push_str("&amp;"),
+            '<' => acc.push_str("&lt;"),
+            '>' => acc.push_str("&gt;"),
+            '"' => acc.push_str("&quot;"),
+            '\'' => acc.push_str("&#39;"),
+            _ => acc.push(c),
+        }
+    }
+    acc
+}
+
+// === RenderOptions ==============================================================================
+
+/// How graphics scripts get series data.
+#[derive(Clone)]
+pub enum SeriesDataMode<'a> {
+
+    /// Each graphic lists the URLs of its series data, for the scripts to fetch.
+    Linked,
+
+    /// Series data is embedded in the page as JSON.
+    Embedded(&'a SeriesDataStore),
+}
+
+/// Everything a page needs apart from its `PageSpec` and template.
+#[derive(Clone)]
+pub struct RenderOptions<'a> {
+
+    /// The URL prefix of the site, ending in `/`. This is `/` when served, and a relative path
+    /// like `../../` when exported.
+    pub root: String,
+
+    /// The names of the scripts in `ts_graphics/js`, without extension.
+    pub scripts: Vec<String>,
+
+    pub series_data: SeriesDataMode<'a>,
+
+    /// If set, each graphic container holds an SVG drawn from the data under this root, for
+    /// readers without Javascript. The static export sets this; the server leaves it unset so
+    /// that loading pages does not draw every graphic.
+    pub svg_root: Option<PathBuf>,
+
+    /// Whether each page has an Open Graph image at `page_image_path`, as written by the static
+    /// export.
+    pub og_image: bool,
+}
+
+impl<'a> RenderOptions<'a> {
+
+    /// Link every script in `ts_graphics/js`, without drawing graphics as SVG.
+    pub fn new<P: AsRef<Path>>(data_root: P, root: &str, series_data: SeriesDataMode<'a>) -> Result<Self> {
+        let mut scripts = Vec::new();
+        for path in TSGraphicsJs.into_resources(&data_root)?.iter() {
+            scripts.push(Key::from_path(&path)?.to_string());
+        }
+        scripts.sort();
+
+        Ok(RenderOptions {
+            root: root.to_string(),
+            scripts,
+            series_data,
+            svg_root: None,
+            og_image: false,
+        })
+    }
+}
+
+// === Rendering ==================================================================================
+
+/// Render a page with the `page.html` template.
+pub fn render_page(page: &PageSpec, templates: &Templates, options: &RenderOptions) -> Result<String> {
+    Ok(templates.render(PAGE_TEMPLATE, &page_context(page, options)?)?)
+}
+
+/// The template variables for a page.
+pub fn page_context(page: &PageSpec, options: &RenderOptions) -> Result<Context> {
+    let mut context = Context::new();
+    let title = format!("{} {}", page.country, page.data_type.title());
+
+    context.insert("title".into(), title.into());
+    context.insert("country".into(), page.country.to_string().into());
+    context.insert("country_path".into(), page.country.as_filepath().into());
+    context.insert("data_type".into(), page.data_type.to_string().into());
+    context.insert("data_type_title".into(), page.data_type.title().into());
+    context.insert("index".into(), page.index.to_string().into());
+    context.insert("root".into(), options.root.clone().into());
+    if let Some(height) = page.height_opt {
+        context.insert("height".into(), height.to_string().into());
+    }
+    if options.og_image {
+        let src = format!("{}{}", options.root, page_image_path(page.data_type, page.country, page.index));
+        context.insert("og_image".into(), src.into());
+    }
+
+    let graphics: Vec<Value> = page.graphics
+        .iter()
+        .enumerate()
+        .map(|(i, graphic)| Ok(graphic_context(page, i, graphic, options)?.into()))
+        .collect::<Result<_>>()?;
+    context.insert("graphics".into(), graphics.into());
+
+    let seriess: Vec<Value> = page.seriess
+        .iter()
+        .map(|series| {
+            let mut map = Context::new();
+            map.insert("series_id".into(), series.series_id.to_string().into());
+            map.insert("data_type".into(), series.data_type.to_string().into());
+            map.insert("src".into(), series_src(page, &series.series_id, options).into());
+            map.into()
+        })
+        .collect();
+    context.insert("seriess".into(), seriess.into());
+
+    let scripts: Vec<Value> = options.scripts
+        .iter()
+        .map(|script| {
+            let mut map = Context::new();
+            map.insert("name".into(), script.clone().into());
+            map.insert("src".into(), format!("{}js/{}.js", options.root, script).into());
+            map.into()
+        })
+        .collect();
+    context.insert("scripts".into(), scripts.into());
+
+    context.insert("data".into(), data_context(page, options)?.into());
+    Ok(context)
+}
+
+// Where a graphic script finds the data of a series.
+fn series_src(page: &PageSpec, series_id: &SeriesId, options: &RenderOptions) -> String {
+    match options.series_data {
+        SeriesDataMode::Linked => {
+            format!("{}{}", options.root, series_data_path(page.series_data_type(series_id), page.country, series_id))
+        },
+        SeriesDataMode::Embedded(_) => format!("series-{}", series_id),
+    }
+}
+
+// The template variables for one graphic.
+fn graphic_context(page: &PageSpec, i: usize, graphic: &GraphicSpec, options: &RenderOptions) -> Result<Context> {
+    let mut map = Context::new();
+    map.insert("id".into(), format!("graphic-{}", i).into());
+    if let Some(category) = &graphic.category_opt {
+        map.insert("category".into(), category.to_string().into());
+    }
+    if let Some(range) = &graphic.graphic_range {
+        map.insert("range".into(), range.to_string().into());
+    }
+    if let Some(note) = &graphic.note {
+        map.insert("note".into(), note.clone().into());
+    }
+
+    let series: Vec<Value> = graphic.series_ids
+        .iter()
+        .map(|series_id| {
+            let mut series = Context::new();
+            series.insert("series_id".into(), series_id.to_string().into());
+            series.insert("src".into(), series_src(page, series_id, options).into());
+            series.into()
+        })
+        .collect();
+    map.insert("series".into(), series.into());
+    let svg = match &options.svg_root {
+        Some(root) => {
+            let mut svg_options = SvgOptions { id: format!("graphic-{}-svg", i), ..SvgOptions::default() };
+            if let Some(height) = page.height_opt {
+                svg_options.height = height;
+            }
+            graphic_svg(root, page, graphic, &svg_options)?
+        },
+        None => None,
+    };
+    if let Some(svg) = &svg {
+        map.insert("svg".into(), svg.clone().into());
+    }
+    map.insert("html".into(), render_graphic(page, i, graphic, svg.as_deref(), options).into());
+    Ok(map)
+}
+
+/// Draw a graphic of a page as SVG from the data on disk. Series without data are left out, and a
+/// graphic without any data is not drawn. A graphic without a range gets one from
+/// `GraphicRange::auto`.
+pub fn graphic_svg(
+    data_root: &Path,
+    page: &PageSpec,
+    graphic: &GraphicSpec,
+    options: &SvgOptions) -> Result<Option<String>>
+{
+    let data = page.graphic_data(data_root, graphic)?;
+    if data.iter().all(|(_, series_data)| series_data.is_empty()) {
+        return Ok(None)
+    }
+    let seriess: Vec<(&SeriesId, &SeriesData)> = data
+        .iter()
+        .map(|(series_id, series_data)| (*series_id, series_data))
+        .collect();
+
+    let mut options = options.clone();
+    if options.range.is_none() {
+        let values = data.iter().flat_map(|(_, series_data)| series_data.values.iter().copied());
+        options.range = GraphicRange::auto(page.data_type, values);
+    }
+    Ok(Some(render_svg(graphic, &seriess, &options)?))
+}
+
+// A container for one graphic.
+fn render_graphic(
+    page: &PageSpec,
+    i: usize,
+    graphic: &GraphicSpec,
+    svg: Option<&str>,
+    options: &RenderOptions) -> String
+{
+    let mut s = format!("<div class=\"graphic\" id=\"graphic-{}\"", i);
+
+    if let Some(category) = &graphic.category_opt {
+        s.push_str(&format!(" data-category=\"{}\"", category));
+    }
+    if let Some(range) = &graphic.graphic_range {
+        s.push_str(&format!(" data-range=\"{}\"", range));
+    }
+
+    let series: Vec<String> = graphic.series_ids
+        .iter()
+        .map(|series_id| series_src(page, series_id, options))
+        .collect();
+    s.push_str(&format!(" data-series=\"{}\"", escape(&series.join(" "))));
+
+    if let Some(height) = page.height_opt {
+        s.push_str(&format!(" style=\"height: {}px\"", height));
+    }
+    s.push_str(">\n");
+
+    // Scripts replace the contents of the plot.
+    s.push_str(&format!("<div class=\"plot\">{}</div>\n", svg.unwrap_or("")));
+    if let Some(note) = &graphic.note {
+        s.push_str(&format!("<p class=\"note\">{}</p>\n", escape(note)));
+    }
+    s.push_str("</div>\n");
+    s
+}
+
+// The data of every series on the page or its graphics, if series data is embedded.
+fn data_context(page: &PageSpec, options: &RenderOptions) -> Result<Vec<Value>> {
+    let store = match options.series_data {
+        SeriesDataMode::Linked => return Ok(Vec::new()),
+        SeriesDataMode::Embedded(store) => store,
+    };
+
+    let mut acc = Vec::new();
+    for key in page.series_keys() {
+        let series_data = store
+            .series(&key)
+            .ok_or(anyhow!("No data for series [{}]", key.2))?;
+
+        // The JSON is inserted into a script element unescaped, and only `</` can end the
+        // element early.
+        let json = series_data.to_json()?.replace("</", "<\\/");
+
+        let mut map = Context::new();
+        map.insert("series_id".into(), key.2.to_string().into());
+        map.insert("json".into(), json.into());
+        acc.push(map.into());
+    }
+    Ok(acc)
+}
+
+// === Pages ======================================================================================
+
+/// Every page in the `ts_graphics/spec` directory, rendered with linked series data.
+pub struct Pages(BTreeMap<PageKey, EncodedBody>);
+
+impl Pages {
+    pub fn new<P: AsRef<Path>>(data_root: P) -> Result<Self> {
+        let root = data_root.as_ref();
+        let templates = Templates::new(root)?;
+        let options = RenderOptions::new(root, "/", SeriesDataMode::Linked)?;
+
+        let mut map = BTreeMap::new();
+        for page in ts_spec_from_resources(root)?.pages.iter() {
+            let html = render_page(page, &templates, &options)?;
+            let body = EncodedBody::new("text/html; charset=utf-8", html.into_bytes())?;
+            map.insert((page.data_type, page.country, page.index), body);
+        }
+        Ok(Pages(map))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &PageKey> {
+        self.0.keys()
+    }
+}
+
+impl Reload for Pages {
+    fn reload(&self, data_root: &Path) -> Result<Self> {
+        Pages::new(data_root)
+    }
+
+    fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>> {
+        Ok(
+            vec!(
+                TSPageSpec.dir(data_root)?,
+                TSHtmlTemplate.dir(data_root)?,
+                TSGraphicsJs.dir(data_root)?,
+            )
+        )
+    }
+}
+
+impl HttpState for Pages {
+    type Key = PageKey;
+
+    fn get(&self, key: PageKey) -> HttpResponse {
+        match self.0.get(&key) {
+            Some(body) => body.response(),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    fn respond(&self, key: PageKey, req: &HttpRequest) -> HttpResponse {
+        match self.0.get(&key) {
+            Some(body) => body.respond(req),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use key_tree::KeyTree;
+    use std::fs;
+
+    fn page() -> PageSpec {
+        let s = r#"
+          page:
+              country:        New Zealand
+              data_type:      u
+              index:          0
+              height:         300
+
+              series:
+                  data_type:  u
+                  series_id:  LRHUTTTTNZQ156S
+
+              graphic:
+                  category:   source
+                  series_id:  LRHUTTTTNZQ156S
+                  note:       Data <before> 1986 is annual.
+        "#;
+        KeyTree::parse_str(s).unwrap().try_into().unwrap()
+    }
+
+    fn options() -> RenderOptions<'static> {
+        RenderOptions {
+            root:           "/".to_string(),
+            scripts:        vec!("graphic".to_string()),
+            series_data:    SeriesDataMode::Linked,
+            svg_root:       None,
+            og_image:       false,
+        }
+    }
+
+    #[test]
+    fn page_should_render_into_template() {
+        let templates = Templates::from_sources(vec!((
+            "page.html",
+            "<title>{{ title }}</title>\
+            {% for graphic in graphics %}{{{ graphic.html }}}{% endfor %}\
+            {% for script in scripts %}<script src=\"{{ script.src }}\"></script>{% endfor %}",
+        ))).unwrap();
+        let html = render_page(&page(), &templates, &options()).unwrap();
+
+        assert!(html.starts_with("<title>New Zealand Unemployment</title>"));
+        assert!(html.contains("data-series=\"/data/u/new_zealand/LRHUTTTTNZQ156S.json\""));
+        assert!(html.contains("style=\"height: 300px\""));
+        assert!(html.contains("<p class=\"note\">Data &lt;before&gt; 1986 is annual.</p>"));
+        assert!(html.contains("<script src=\"/js/graphic.js\"></script>"));
+    }
+
+    #[test]
+    fn every_series_of_every_graphic_should_be_embedded() {
+        let root = std::env::temp_dir()
+            .join(format!("graphics_pipeline_html_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["specs", "raw_data/u/australia", "transformed_data/u/australia"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::create_dir_all(root.join("ts_graphics/spec")).unwrap();
+        fs::write(root.join("specs/series_spec.keytree"), "seriess:
+    series:
+        data_type:  u
+        country:    Australia
+        series_id:  AUSURAMS
+").unwrap();
+        fs::write(root.join("raw_data/u/australia/AUSURAMS.csv"), "2000-01-01,5\n").unwrap();
+        fs::write(root.join("transformed_data/u/australia/AUSURAMS_a.csv"), "2000-01-01,5\n")
+            .unwrap();
+
+        let page_s = "
+            page:
+                country:        Australia
+                data_type:      u
+                index:          0
+
+                series:
+                    data_type:  u
+                    series_id:  AUSURAMS
+
+                graphic:
+                    category:   cleaned
+                    series_id:  AUSURAMS_a
+        ";
+        fs::write(root.join("ts_graphics/spec/ts_page_spec.keytree"), page_s).unwrap();
+        let page: PageSpec = KeyTree::parse_str(page_s).unwrap().try_into().unwrap();
+
+        let store = SeriesDataStore::new(&root, "series_spec.keytree").unwrap();
+        let options = RenderOptions { series_data: SeriesDataMode::Embedded(&store), ..options() };
+        let ids: Vec<Value> = data_context(&page, &options)
+            .unwrap()
+            .into_iter()
+            .map(|value| match value {
+                Value::Map(map) => map["series_id"].clone(),
+                _ => panic!("Expected a map"),
+            })
+            .collect();
+        assert_eq!(ids, vec!(Value::from("AUSURAMS"), Value::from("AUSURAMS_a")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn page_path_should_use_filepath_country() {
+        assert_eq!(page_path(DataType::Inf, Country::NewZealand, 2), "inf/new_zealand/2.html");
+    }
+}
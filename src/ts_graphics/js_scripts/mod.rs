@@ -1,13 +1,16 @@
 /// Loads all the JS scripts in a HashMap that returns Strings.
 
-use actix_web::HttpResponse;
-use anyhow::{bail, Result};
+use actix_web::{HttpRequest, HttpResponse};
+use anyhow::anyhow;
 use crate::{
+    error::Result,
     file_resources::IntoResources,
     file_resources::impls::{
         TSGraphicsJs,
     },
     http_state::HttpState,
+    http_state::encoded::EncodedBody,
+    http_state::hot_reload::Reload,
 };
 use std::{collections::HashMap, fmt, fs, path::{Path, PathBuf}, str::FromStr};
 
@@ -20,10 +23,10 @@ impl Key {
             Some(os_str) => {
                 match os_str.to_str() {
                     Some(s) => Ok(Key(s.to_string())),
-                    None => bail!("Could not convert path to string."),
+                    None => Err(anyhow!("Could not convert path to string.").into()),
                 }
             },
-            None => bail!("Found empty path."),
+            None => Err(anyhow!("Found empty path.").into()),
         }
     }
 }
@@ -43,7 +46,7 @@ impl fmt::Display for Key {
 }
 
 /// Keys are the short filename without the extension.
-pub struct JsScripts(HashMap<Key, String>);
+pub struct JsScripts(HashMap<Key, EncodedBody>);
 
 impl JsScripts {
     pub fn new<P: AsRef<Path>>(data_root: P) -> Result<Self> {
@@ -52,24 +55,37 @@ impl JsScripts {
 
         for path in TSGraphicsJs.into_resources(pb)?.iter() {
             let key = Key::from_path(&path)?; 
-            let value = fs::read_to_string(path)?;
+            let value = EncodedBody::new("text/javascript", fs::read(path)?)?;
             hm.insert(key, value);
         }
         Ok(JsScripts(hm))
     }
 }
 
+impl Reload for JsScripts {
+    fn reload(&self, data_root: &Path) -> Result<Self> {
+        JsScripts::new(data_root)
+    }
+
+    fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>> {
+        Ok(vec!(TSGraphicsJs.dir(data_root)?))
+    }
+}
+
 impl HttpState for JsScripts {
     /// The filename without the extension
     type Key = Key;
 
     fn get(&self, key: Key) -> HttpResponse {
         match self.0.get(&key) {
-            Some(s) => {
-                HttpResponse::Ok()
-                    .content_type("text/javascript")
-                    .body(s.clone())
-            },
+            Some(body) => body.response(),
+            None => not_found()
+        }
+    }
+
+    fn respond(&self, key: Key, req: &HttpRequest) -> HttpResponse {
+        match self.0.get(&key) {
+            Some(body) => body.respond(req),
             None => not_found()
         }
     }
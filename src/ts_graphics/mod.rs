@@ -1,5 +1,17 @@
 
+/// Render time-series pages into HTML.
+pub mod html;
 pub mod js_scripts;
+
+/// Rasterise graphics to PNG.
+pub mod png;
+pub mod series_data;
+
+/// Draw graphics as SVG on the server.
+pub mod svg;
+
+/// Variables, loops and includes for HTML templates.
+pub mod template;
 pub mod ts_spec;
 
 use anyhow::{anyhow, Error, Result};
@@ -8,7 +20,7 @@ use std::{fmt, str::FromStr};
 
 // === GraphicCategory ============================================================================
 
-#[derive(Debug, PartialEq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum TSGraphicCategory {
     /// Generally the top graphic which displays all time-series. 
     Collation,
@@ -0,0 +1,139 @@
+//! Rasterise graphics to PNG on the CPU, for social cards and reports.
+//!
+//! Graphics are drawn as SVG by [`svg`](../svg/index.html) and rasterised by `resvg`, so no
+//! browser or GPU is needed. Sizes are in CSS pixels, which are `1/96` inch, and the DPI scales
+//! the bitmap, so that a `800 x 400` graphic at `192` DPI is `1600 x 800` pixels.
+
+use anyhow::anyhow;
+use crate::{
+    error::Result,
+    primitives::SeriesId,
+    ts_graphics::series_data::SeriesData,
+    ts_graphics::svg::{render_svg, SvgOptions},
+    ts_graphics::ts_spec::GraphicSpec,
+};
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, fontdb, TreeParsing, TreeTextToPath},
+};
+use std::sync::OnceLock;
+
+/// The resolution of CSS pixels.
+pub const DEFAULT_DPI: f32 = 96.0;
+
+// === PngOptions =================================================================================
+
+/// The size in CSS pixels and the resolution of a PNG.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PngOptions {
+    pub width:  f32,
+    pub height: f32,
+    pub dpi:    f32,
+}
+
+impl PngOptions {
+
+    /// The size of an Open Graph image, as used by social cards.
+    pub fn open_graph() -> Self {
+        PngOptions { width: 1200.0, height: 630.0, dpi: DEFAULT_DPI }
+    }
+
+    /// The size of the bitmap in pixels.
+    /// ```
+    /// # use graphics_pipeline::ts_graphics::png::PngOptions;
+    /// let options = PngOptions { width: 800.0, height: 400.0, dpi: 192.0 };
+    /// assert_eq!(options.pixels(), (1600, 800));
+    /// ```
+    pub fn pixels(&self) -> (u32, u32) {
+        let scale = self.dpi / DEFAULT_DPI;
+        (
+            (self.width * scale).round().max(1.0) as u32,
+            (self.height * scale).round().max(1.0) as u32,
+        )
+    }
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        let svg = SvgOptions::default();
+        PngOptions { width: svg.width, height: svg.height, dpi: DEFAULT_DPI }
+    }
+}
+
+// === Rendering ==================================================================================
+
+/// Render a graphic given the data of each of its series, in the order of `graphic.series_ids`.
+pub fn render_png(
+    graphic: &GraphicSpec,
+    seriess: &[(&SeriesId, &SeriesData)],
+    options: &PngOptions) -> Result<Vec<u8>>
+{
+    let svg_options = SvgOptions {
+        width:  options.width,
+        height: options.height,
+        ..SvgOptions::default()
+    };
+    svg_to_png(&render_svg(graphic, seriess, &svg_options)?, options)
+}
+
+// The system fonts, which are slow to load, so they are loaded once and shared by every image.
+fn system_fonts() -> &'static fontdb::Database {
+    static FONTS: OnceLock<fontdb::Database> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut fonts = fontdb::Database::new();
+        fonts.load_system_fonts();
+        fonts
+    })
+}
+
+/// Rasterise an SVG, stretching it to the size in `options`. Text is drawn with the system fonts.
+pub fn svg_to_png(svg: &str, options: &PngOptions) -> Result<Vec<u8>> {
+    let mut tree = usvg::Tree::from_str(svg, &usvg::Options::default())
+        .map_err(|e| anyhow!("Failed to parse SVG: {}", e))?;
+
+    tree.convert_text(system_fonts());
+
+    let (width, height) = options.pixels();
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or(anyhow!("Failed to allocate a {} x {} bitmap", width, height))?;
+
+    let transform = Transform::from_scale(
+        width as f32 / tree.size.width(),
+        height as f32 / tree.size.height(),
+    );
+    resvg::Tree::from_usvg(&tree).render(transform, &mut pixmap.as_mut());
+
+    pixmap.encode_png().map_err(|e| anyhow!("Failed to encode PNG: {}", e).into())
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    // The width and height from the IHDR chunk.
+    fn png_size(png: &[u8]) -> (u32, u32) {
+        let width = u32::from_be_bytes([png[16], png[17], png[18], png[19]]);
+        let height = u32::from_be_bytes([png[20], png[21], png[22], png[23]]);
+        (width, height)
+    }
+
+    #[test]
+    fn png_should_be_scaled_by_dpi() {
+        let graphic = GraphicSpec {
+            category_opt:   None,
+            series_ids:     vec!(SeriesId::new("AUSURAMS")),
+            graphic_range:  None,
+            note:           Some("Monthly.".to_string()),
+        };
+        let series_id = SeriesId::new("AUSURAMS");
+        let data = SeriesData::from_csv("2000-01-01,5\n2005-01-01,7\n2010-01-01,6\n").unwrap();
+
+        let options = PngOptions { width: 300.0, height: 200.0, dpi: 192.0 };
+        let png = render_png(&graphic, &[(&series_id, &data)], &options).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(png_size(&png), (600, 400));
+    }
+}
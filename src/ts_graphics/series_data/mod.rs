@@ -0,0 +1,346 @@
+//! Serves the values of each series as JSON for the graphic scripts.
+//!
+//! Data is loaded from `transformed_data/`, falling back to `raw_data/` for series which have not
+//! been transformed yet. A response looks like
+//! ```text
+//! {
+//!     "dates": ["1960-01-01", "1960-02-01"],
+//!     "values": [3.2, 3.1],
+//!     "meta": { "title": "Unemployment Rate: Aged 15-64: All Persons for Australia", .. }
+//! }
+//! ```
+//! and the query `?from=1990-01-01&to=2000-01-01&points=50` restricts the dates to a range and
+//! reduces the number of points by averaging.
+
+use actix_web::web;
+use anyhow::anyhow;
+use crate::{
+    countries::Country,
+    error::{Error, Result},
+    file_resources::{from_path_arg, IntoResources},
+    file_resources::impls::{CsvRawData, CsvTransformedData, MetaData, Spec, TSPageSpec},
+    http_state::{HttpRequest, HttpResponse, HttpState},
+    http_state::encoded::EncodedBody,
+    http_state::hot_reload::Reload,
+    meta_data,
+    primitives::{DataType, SeriesId},
+    series_to_disk::spec_map_from_spec,
+    ts_graphics::ts_spec::ts_spec_from_resources,
+};
+use key_tree::KeyTree;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// Identifies a series in the store.
+pub type SeriesKey = (DataType, Country, SeriesId);
+
+// === SeriesData =================================================================================
+
+/// The dates and values of a series, with its metadata if there is a `.meta` file.
+#[derive(Clone, Debug, Serialize)]
+pub struct SeriesData {
+    pub dates:  Vec<String>,
+    pub values: Vec<f32>,
+    pub meta:   Option<meta_data::Series>,
+}
+
+impl SeriesData {
+
+    /// Read CSV data with a date column and a value column, such as FRED data. A header line is
+    /// skipped, and values of `.` which FRED uses for missing data are ignored.
+    /// ```
+    /// # use graphics_pipeline::ts_graphics::series_data::SeriesData;
+    /// let data = SeriesData::from_csv("DATE,VALUE\n2020-01-01,5.1\n2020-02-01,.\n2020-03-01,5.3\n").unwrap();
+    /// assert_eq!(data.values, vec!(5.1, 5.3));
+    /// ```
+    pub fn from_csv(s: &str) -> Result<Self> {
+        let mut dates = Vec::new();
+        let mut values = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() { continue }
+
+            let mut fields = line.split(',');
+            let date = fields.next().unwrap_or("").trim();
+            let value = fields.next()
+                .ok_or(anyhow!("Line {} [{}] has no value", i + 1, line))?
+                .trim();
+
+            if i == 0 && value.parse::<f32>().is_err() { continue }
+            if value == "." { continue }
+
+            values.push(
+                value.parse().map_err(|_| anyhow!("Line {} [{}] has a bad value", i + 1, line))?
+            );
+            dates.push(date.to_string());
+        }
+        Ok(SeriesData { dates, values, meta: None })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Return the points with `from <= date <= to`. Dates are `YYYY-MM-DD` so they compare as
+    /// strings.
+    pub fn window(&self, from: Option<&str>, to: Option<&str>) -> SeriesData {
+        let mut dates = Vec::new();
+        let mut values = Vec::new();
+        for (date, value) in self.dates.iter().zip(self.values.iter()) {
+            if from.map_or(false, |from| date.as_str() < from) { continue }
+            if to.map_or(false, |to| date.as_str() > to) { continue }
+            dates.push(date.clone());
+            values.push(*value);
+        }
+        SeriesData { dates, values, meta: self.meta.clone() }
+    }
+
+    /// Reduce the series to at most `points` points by averaging consecutive values. Each point
+    /// takes the date of the first value it averages.
+    /// ```
+    /// # use graphics_pipeline::ts_graphics::series_data::SeriesData;
+    /// let data = SeriesData::from_csv("2020-01-01,1\n2020-02-01,3\n2020-03-01,5\n").unwrap();
+    /// let reduced = data.downsample(2);
+    /// assert_eq!(reduced.dates, vec!("2020-01-01", "2020-03-01"));
+    /// assert_eq!(reduced.values, vec!(2.0, 5.0));
+    /// ```
+    pub fn downsample(&self, points: usize) -> SeriesData {
+        if points == 0 || self.len() <= points {
+            return self.clone()
+        }
+        let chunk = (self.len() + points - 1) / points;
+
+        let dates = self.dates.chunks(chunk).map(|c| c[0].clone()).collect();
+        let values = self.values
+            .chunks(chunk)
+            .map(|c| c.iter().sum::<f32>() / c.len() as f32)
+            .collect();
+
+        SeriesData { dates, values, meta: self.meta.clone() }
+    }
+
+    /// Apply a query from the request.
+    pub fn query(&self, query: &SeriesQuery) -> SeriesData {
+        let windowed = self.window(query.from.as_deref(), query.to.as_deref());
+        match query.points {
+            Some(points) => windowed.downsample(points),
+            None => windowed,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| Error::Other(e.into()))
+    }
+}
+
+// === SeriesQuery ================================================================================
+
+/// The query string of a request for series data.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct SeriesQuery {
+    pub from:   Option<String>,
+    pub to:     Option<String>,
+    pub points: Option<usize>,
+}
+
+impl SeriesQuery {
+    pub fn is_empty(&self) -> bool {
+        self == &SeriesQuery::default()
+    }
+}
+
+// === SeriesDataStore ============================================================================
+
+/// Every series in the series specification or on a graphic of a page which has data on disk.
+pub struct SeriesDataStore {
+    series_spec_file: PathBuf,
+    data: BTreeMap<SeriesKey, (SeriesData, EncodedBody)>,
+}
+
+impl SeriesDataStore {
+
+    /// Load the data for every series in the series specification, and every series on a graphic
+    /// in `ts_graphics/spec`, which includes transformed series such as `AUSURAMS_a`. Series
+    /// without data are left out and answered with `404 Not Found`; use `verify_raw` to find them.
+    pub fn new<P, S>(data_root: P, series_spec_file: S) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        S: AsRef<OsStr>,
+    {
+        let root: PathBuf = from_path_arg(data_root);
+        let spec_file = PathBuf::from(series_spec_file.as_ref());
+        let spec_map = spec_map_from_spec(&root, &spec_file)?;
+
+        let mut keys: BTreeSet<SeriesKey> = spec_map
+            .iter()
+            .map(|series_spec| {
+                (series_spec.data_type(), series_spec.country(), series_spec.series_id())
+            })
+            .collect();
+        if TSPageSpec.dir(&root).is_ok() {
+            for page in ts_spec_from_resources(&root)?.pages.iter() {
+                for graphic in page.graphics() {
+                    for series_id in graphic.series_ids.iter() {
+                        let data_type = page.series_data_type(series_id);
+                        keys.insert((data_type, page.country(), series_id.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut data = BTreeMap::new();
+        for key in keys {
+            if let Some(series_data) = load_series(&root, &key)? {
+                let body = EncodedBody::new("application/json", series_data.to_json()?.into_bytes())?;
+                data.insert(key, (series_data, body));
+            }
+        }
+        Ok(SeriesDataStore { series_spec_file: spec_file, data })
+    }
+
+    pub fn series(&self, key: &SeriesKey) -> Option<&SeriesData> {
+        self.data.get(key).map(|(series_data, _)| series_data)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &SeriesKey> {
+        self.data.keys()
+    }
+
+    /// The JSON response body for a series, without a query.
+    pub fn json(&self, key: &SeriesKey) -> Option<&EncodedBody> {
+        self.data.get(key).map(|(_, body)| body)
+    }
+}
+
+/// Load the data for a series from `transformed_data/`, falling back to `raw_data/`, with the
+/// metadata from the `.meta` file if there is one.
+pub fn load_series(data_root: &Path, key: &SeriesKey) -> Result<Option<SeriesData>> {
+    let (data_type, country, series_id) = key;
+    let csv = PathBuf::from(series_id.to_string()).with_extension("csv");
+
+    let transformed = CsvTransformedData { country: *country, data_type: *data_type };
+    let raw = CsvRawData { country: *country, data_type: *data_type };
+
+    let s = if transformed.has_file(data_root, &csv).unwrap_or(false) {
+        transformed.from_file(data_root, &csv)?
+    } else if raw.has_file(data_root, &csv).unwrap_or(false) {
+        raw.from_file(data_root, &csv)?
+    } else {
+        return Ok(None)
+    };
+    let mut series_data = SeriesData::from_csv(&s)?;
+
+    let meta_file = PathBuf::from(series_id.stem().to_string()).with_extension("meta");
+    let meta_data = MetaData { country: *country, data_type: *data_type };
+    if meta_data.has_file(data_root, &meta_file).unwrap_or(false) {
+        let meta: meta_data::Series = KeyTree::parse(meta_data.full_path(data_root, &meta_file)?)?
+            .try_into()?;
+        series_data.meta = Some(meta);
+    }
+    Ok(Some(series_data))
+}
+
+impl Reload for SeriesDataStore {
+    fn reload(&self, data_root: &Path) -> Result<Self> {
+        SeriesDataStore::new(data_root, &self.series_spec_file)
+    }
+
+    fn watch_dirs(&self, data_root: &Path) -> Result<Vec<PathBuf>> {
+        let mut acc = vec!(Spec.dir(data_root)?);
+        if let Ok(dir) = TSPageSpec.dir(data_root) {
+            acc.push(dir);
+        }
+
+        // The data directories rather than their buckets, so that new buckets are watched.
+        for dir in ["raw_data", "transformed_data"] {
+            if data_root.join(dir).is_dir() {
+                acc.push(data_root.join(dir));
+            }
+        }
+        Ok(acc)
+    }
+}
+
+impl HttpState for SeriesDataStore {
+    type Key = SeriesKey;
+
+    fn get(&self, key: SeriesKey) -> HttpResponse {
+        match self.json(&key) {
+            Some(body) => body.response(),
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    fn respond(&self, key: SeriesKey, req: &HttpRequest) -> HttpResponse {
+        let query = match web::Query::<SeriesQuery>::from_query(req.query_string()) {
+            Ok(query) => query.into_inner(),
+            Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+        };
+
+        match self.data.get(&key) {
+            Some((_, body)) if query.is_empty() => body.respond(req),
+            Some((series_data, _)) => {
+                match series_data.query(&query).to_json() {
+                    Ok(json) => {
+                        HttpResponse::Ok()
+                            .content_type("application/json")
+                            .body(json)
+                    },
+                    Err(_) => HttpResponse::InternalServerError().finish(),
+                }
+            },
+            None => HttpResponse::NotFound().finish(),
+        }
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn data() -> SeriesData {
+        SeriesData::from_csv(
+            "DATE,VALUE\n2020-01-01,1\n2020-02-01,2\n2020-03-01,3\n2020-04-01,4\n"
+        ).unwrap()
+    }
+
+    #[test]
+    fn window_should_include_bounds() {
+        let windowed = data().window(Some("2020-02-01"), Some("2020-03-01"));
+        assert_eq!(windowed.values, vec!(2.0, 3.0));
+    }
+
+    #[test]
+    fn query_should_window_then_downsample() {
+        let query = SeriesQuery {
+            from:   Some("2020-02-01".to_string()),
+            to:     None,
+            points: Some(2),
+        };
+        let output = data().query(&query);
+        assert_eq!(output.dates, vec!("2020-02-01", "2020-04-01"));
+        assert_eq!(output.values, vec!(2.5, 4.0));
+    }
+
+    #[test]
+    fn bad_value_should_fail() {
+        assert!(SeriesData::from_csv("2020-01-01,1\n2020-02-01,x\n").is_err());
+    }
+
+    #[test]
+    fn json_should_have_dates_values_and_meta() {
+        let json = SeriesData::from_csv("2020-01-01,1\n").unwrap().to_json().unwrap();
+        assert_eq!(json, r#"{"dates":["2020-01-01"],"values":[1.0],"meta":null}"#);
+    }
+}
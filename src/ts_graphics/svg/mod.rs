@@ -0,0 +1,335 @@
+//! Render a [`GraphicSpec`](../ts_spec/struct.GraphicSpec.html) as SVG on the server.
+//!
+//! The SVG has date ticks along the x-axis, a y-axis ranging over the `GraphicRange` (or the data
+//! if the graphic has no range), one line per series, a legend of series titles from the metadata,
+//! and the note as a caption. Pages include it inside each graphic container so that they can be
+//! read without Javascript, and it can be embedded in reports.
+
+use anyhow::anyhow;
+use crate::{
+    error::Result,
+    primitives::SeriesId,
+    ts_graphics::html::escape,
+    ts_graphics::series_data::SeriesData,
+    ts_graphics::ts_spec::{GraphicRange, GraphicSpec},
+};
+use std::fmt::Write;
+
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+const MARGIN_LEFT: f32 = 50.0;
+const MARGIN_RIGHT: f32 = 20.0;
+const MARGIN_TOP: f32 = 20.0;
+const MARGIN_BOTTOM: f32 = 30.0;
+const LINE_HEIGHT: f32 = 18.0;
+
+/// Convert a date like `1990-07-01` into a year with a fraction, like `1990.5`.
+/// ```
+/// # use graphics_pipeline::ts_graphics::svg::decimal_year;
+/// assert_eq!(decimal_year("1990-07-01"), Some(1990.5));
+/// ```
+pub fn decimal_year(date: &str) -> Option<f32> {
+    let mut parts = date.split('-');
+    let year: f32 = parts.next()?.parse().ok()?;
+    let month: f32 = parts.next().map_or(Some(1.0), |m| m.parse().ok())?;
+    let day: f32 = parts.next().map_or(Some(1.0), |d| d.parse().ok())?;
+    Some(year + (month - 1.0) / 12.0 + (day - 1.0) / 365.0)
+}
+
+/// A round step between ticks, such as 0.5, 1, 2, 5 or 10, so that `span` has at most about
+/// `max_ticks` ticks.
+/// ```
+/// # use graphics_pipeline::ts_graphics::svg::nice_step;
+/// assert_eq!(nice_step(12.0, 5), 5.0);
+/// ```
+pub fn nice_step(span: f32, max_ticks: usize) -> f32 {
+    let raw = span / max_ticks.max(1) as f32;
+    if raw <= 0.0 || !raw.is_finite() {
+        return 1.0
+    }
+    let magnitude = 10f32.powf(raw.log10().floor());
+    let residual = raw / magnitude;
+    let nice = if residual <= 1.0 {
+        1.0
+    } else if residual <= 2.0 {
+        2.0
+    } else if residual <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+// Format a tick label with as many decimal places as the step needs.
+fn format_tick(value: f32, step: f32) -> String {
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+// === SvgOptions =================================================================================
+
+/// The size of the SVG, and an id which must be unique on the page the SVG is embedded in.
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    pub id:     String,
+    pub width:  f32,
+    pub height: f32,
+
+    /// The y-range used if the graphic has none, such as one from `GraphicRange::auto`. Without
+    /// either, the y-axis spans the data.
+    pub range:  Option<GraphicRange>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        SvgOptions {
+            id:     "graphic".to_string(),
+            width:  800.0,
+            height: 400.0,
+            range:  None,
+        }
+    }
+}
+
+// === Rendering ==================================================================================
+
+/// Render a graphic given the data of each of its series, in the order of `graphic.series_ids`.
+pub fn render_svg(
+    graphic: &GraphicSpec,
+    seriess: &[(&SeriesId, &SeriesData)],
+    options: &SvgOptions) -> Result<String>
+{
+    let lines: Vec<Vec<(f32, f32)>> = seriess
+        .iter()
+        .map(|(_, data)| {
+            data.dates
+                .iter()
+                .zip(data.values.iter())
+                .filter_map(|(date, value)| Some((decimal_year(date)?, *value)))
+                .collect()
+        })
+        .collect();
+
+    let all = lines.iter().flatten();
+    let (mut x_min, mut x_max) = (f32::MAX, f32::MIN);
+    let (mut y_min, mut y_max) = (f32::MAX, f32::MIN);
+    for (x, y) in all {
+        x_min = x_min.min(*x);
+        x_max = x_max.max(*x);
+        y_min = y_min.min(*y);
+        y_max = y_max.max(*y);
+    }
+    if x_min > x_max {
+        return Err(anyhow!("Graphic has no data to draw.").into())
+    }
+    if let Some(range) = graphic.graphic_range.or(options.range) {
+        y_min = range.min();
+        y_max = range.max();
+    }
+    if x_min == x_max { x_min -= 0.5; x_max += 0.5 }
+    if y_min == y_max { y_min -= 1.0; y_max += 1.0 }
+
+    let plot = Plot::new(options, seriess.len(), graphic.note.is_some(), (x_min, x_max), (y_min, y_max));
+
+    let mut s = String::new();
+    writeln!(
+        s,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+        w = options.width,
+        h = options.height,
+    )?;
+    writeln!(s, r#"<rect width="100%" height="100%" fill="white"/>"#)?;
+    writeln!(
+        s,
+        r#"<defs><clipPath id="{}-clip"><rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}"/></clipPath></defs>"#,
+        escape(&options.id),
+        plot.left,
+        plot.top,
+        plot.right - plot.left,
+        plot.bottom - plot.top,
+    )?;
+
+    plot.write_y_ticks(&mut s)?;
+    plot.write_x_ticks(&mut s)?;
+
+    writeln!(
+        s,
+        r##"<path d="M{l:.1},{t:.1}V{b:.1}H{r:.1}" fill="none" stroke="#000"/>"##,
+        l = plot.left,
+        t = plot.top,
+        b = plot.bottom,
+        r = plot.right,
+    )?;
+
+    writeln!(s, r#"<g clip-path="url(#{}-clip)" fill="none" stroke-width="1.5">"#, escape(&options.id))?;
+    for (i, line) in lines.iter().enumerate() {
+        let points: Vec<String> = line
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", plot.x(*x), plot.y(*y)))
+            .collect();
+        writeln!(s, r#"<polyline stroke="{}" points="{}"/>"#, COLORS[i % COLORS.len()], points.join(" "))?;
+    }
+    writeln!(s, "</g>")?;
+
+    let mut baseline = plot.bottom + MARGIN_BOTTOM;
+    for (i, (series_id, data)) in seriess.iter().enumerate() {
+        baseline += LINE_HEIGHT;
+        let title = match &data.meta {
+            Some(meta) => format!("{} ({})", meta.title(), series_id),
+            None => series_id.to_string(),
+        };
+        writeln!(
+            s,
+            r#"<line x1="{x1:.1}" y1="{y:.1}" x2="{x2:.1}" y2="{y:.1}" stroke="{c}" stroke-width="2"/><text x="{tx:.1}" y="{ty:.1}">{t}</text>"#,
+            x1 = plot.left,
+            x2 = plot.left + 20.0,
+            y = baseline - 4.0,
+            c = COLORS[i % COLORS.len()],
+            tx = plot.left + 26.0,
+            ty = baseline,
+            t = escape(&title),
+        )?;
+    }
+
+    if let Some(note) = &graphic.note {
+        baseline += LINE_HEIGHT;
+        writeln!(s, r#"<text x="{:.1}" y="{:.1}" font-style="italic">{}</text>"#, plot.left, baseline, escape(note))?;
+    }
+
+    writeln!(s, "</svg>")?;
+    Ok(s)
+}
+
+// The plot area and the mapping from data to SVG coordinates.
+struct Plot {
+    left:   f32,
+    right:  f32,
+    top:    f32,
+    bottom: f32,
+    x_range: (f32, f32),
+    y_range: (f32, f32),
+}
+
+impl Plot {
+    fn new(options: &SvgOptions, n: usize, has_note: bool, x_range: (f32, f32), y_range: (f32, f32)) -> Self {
+        let below = n as f32 * LINE_HEIGHT + if has_note { LINE_HEIGHT } else { 0.0 };
+        Plot {
+            left:   MARGIN_LEFT,
+            right:  options.width - MARGIN_RIGHT,
+            top:    MARGIN_TOP,
+            bottom: options.height - MARGIN_BOTTOM - below,
+            x_range,
+            y_range,
+        }
+    }
+
+    fn x(&self, year: f32) -> f32 {
+        let (min, max) = self.x_range;
+        self.left + (year - min) / (max - min) * (self.right - self.left)
+    }
+
+    fn y(&self, value: f32) -> f32 {
+        let (min, max) = self.y_range;
+        self.bottom - (value - min) / (max - min) * (self.bottom - self.top)
+    }
+
+    // Year ticks, at a step of 1, 2, 5, 10, .. years.
+    fn write_x_ticks(&self, s: &mut String) -> Result<()> {
+        let (min, max) = self.x_range;
+        let step = nice_step(max - min, 10).max(1.0);
+
+        writeln!(s, r##"<g text-anchor="middle" stroke="#000">"##)?;
+        let mut year = (min / step).ceil() * step;
+        while year <= max {
+            let x = self.x(year);
+            writeln!(
+                s,
+                r#"<line x1="{x:.1}" y1="{b:.1}" x2="{x:.1}" y2="{b5:.1}"/><text x="{x:.1}" y="{ty:.1}" stroke="none">{year}</text>"#,
+                x = x,
+                b = self.bottom,
+                b5 = self.bottom + 5.0,
+                ty = self.bottom + 18.0,
+                year = year.round() as i32,
+            )?;
+            year += step;
+        }
+        writeln!(s, "</g>")?;
+        Ok(())
+    }
+
+    // Value ticks with grid lines.
+    fn write_y_ticks(&self, s: &mut String) -> Result<()> {
+        let (min, max) = self.y_range;
+        let step = nice_step(max - min, 5);
+
+        writeln!(s, r##"<g text-anchor="end" stroke="#ddd">"##)?;
+        let mut value = (min / step).ceil() * step;
+        while value <= max + step * 1e-3 {
+            let y = self.y(value);
+            writeln!(
+                s,
+                r##"<line x1="{l:.1}" y1="{y:.1}" x2="{r:.1}" y2="{y:.1}"/><text x="{tx:.1}" y="{ty:.1}" stroke="none" fill="#000">{v}</text>"##,
+                l = self.left,
+                r = self.right,
+                y = y,
+                tx = self.left - 6.0,
+                ty = y + 4.0,
+                v = format_tick(value, step),
+            )?;
+            value += step;
+        }
+        writeln!(s, "</g>")?;
+        Ok(())
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn graphic(range: Option<GraphicRange>) -> GraphicSpec {
+        GraphicSpec {
+            category_opt:   None,
+            series_ids:     vec!(SeriesId::new("A"), SeriesId::new("B")),
+            graphic_range:  range,
+            note:           Some("Rates <5%".to_string()),
+        }
+    }
+
+    #[test]
+    fn svg_should_have_one_line_per_series_with_legend_and_note() {
+        let a = SeriesData::from_csv("2000-01-01,1\n2010-01-01,3\n").unwrap();
+        let b = SeriesData::from_csv("2000-01-01,2\n2010-01-01,4\n").unwrap();
+        let (id_a, id_b) = (SeriesId::new("A"), SeriesId::new("B"));
+
+        let svg = render_svg(&graphic(None), &[(&id_a, &a), (&id_b, &b)], &SvgOptions::default()).unwrap();
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains(">2005</text>"));
+        assert!(svg.contains(">Rates &lt;5%</text>"));
+    }
+
+    #[test]
+    fn graphic_range_should_set_y_ticks() {
+        let a = SeriesData::from_csv("2000-01-01,1\n2010-01-01,3\n").unwrap();
+        let id_a = SeriesId::new("A");
+        let svg = render_svg(&graphic(Some(GraphicRange::new(0.0, 10.0))), &[(&id_a, &a)], &SvgOptions::default()).unwrap();
+        assert!(svg.contains(">10</text>"));
+        assert!(svg.contains(">0</text>"));
+    }
+
+    #[test]
+    fn empty_graphic_should_fail() {
+        assert!(render_svg(&graphic(None), &[], &SvgOptions::default()).is_err());
+    }
+
+    #[test]
+    fn tick_labels_should_have_decimals_for_small_steps() {
+        assert_eq!(format_tick(0.5, 0.5), "0.5");
+        assert_eq!(format_tick(10.0, 5.0), "10");
+    }
+}
@@ -0,0 +1,431 @@
+//! A small template engine for the HTML templates in `ts_graphics/templates`.
+//!
+//! | Syntax                                      | Meaning                                      |
+//! |---------------------------------------------|----------------------------------------------|
+//! | `{{ country }}`                             | A variable, HTML escaped                     |
+//! | `{{ graphic.note }}`                        | A field of a variable                        |
+//! | `{{{ graphic.html }}}`                      | A variable, not escaped                      |
+//! | `{% for graphic in graphics %}..{% endfor %}` | Repeat for each item of a list             |
+//! | `{% if graphic.note %}..{% else %}..{% endif %}` | Include if a variable is non-empty      |
+//! | `{% include "header.html" %}`               | Render another template in the same context |
+//!
+//! Templates are parsed when they are loaded, so syntax errors are found before any page is
+//! rendered. Errors report the template file and line.
+//!
+//! ```
+//! # use graphics_pipeline::ts_graphics::template::{Context, Templates, Value};
+//! let templates = Templates::from_sources(vec!(
+//!     ("page.html", "{% include \"header.html\" %}{% for s in seriess %}{{ s }} {% endfor %}"),
+//!     ("header.html", "<h1>{{ country }}</h1>"),
+//! )).unwrap();
+//!
+//! let mut context = Context::new();
+//! context.insert("country".into(), "Côte d'Ivoire".into());
+//! context.insert("seriess".into(), Value::List(vec!("A".into(), "B".into())));
+//!
+//! assert_eq!(
+//!     templates.render("page.html", &context).unwrap(),
+//!     "<h1>Côte d&#39;Ivoire</h1>A B ",
+//! );
+//! ```
+
+use crate::{
+    error::Result,
+    file_resources::{file_name, IntoResources},
+    file_resources::impls::TSHtmlTemplate,
+    ts_graphics::html::escape,
+};
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// Includes can nest to this depth, which stops a template from including itself forever.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The variables available to a template.
+pub type Context = BTreeMap<String, Value>;
+
+// === Value ======================================================================================
+
+/// A template variable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Str(String),
+    List(Vec<Value>),
+    Map(Context),
+}
+
+impl Value {
+
+    // Values which are empty are false in an `if`.
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::List(v) => !v.is_empty(),
+            Value::Map(_) => true,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self { Value::Str(s.to_string()) }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self { Value::Str(s) }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self { Value::List(v) }
+}
+
+impl From<Context> for Value {
+    fn from(map: Context) -> Self { Value::Map(map) }
+}
+
+// === TemplateError ==============================================================================
+
+/// An error in a template, at a line of a template file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateError {
+    pub file:       String,
+    pub line:       usize,
+    pub message:    String,
+}
+
+impl TemplateError {
+    fn new(file: &str, line: usize, message: String) -> Self {
+        TemplateError { file: file.to_string(), line, message }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+// === Parsing ====================================================================================
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Var { path: String, raw: bool },
+    Tag(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Var { path: String, raw: bool, line: usize },
+    For { name: String, path: String, body: Vec<Node>, line: usize },
+    If { path: String, then: Vec<Node>, otherwise: Vec<Node>, line: usize },
+    Include { name: String, line: usize },
+}
+
+// Split a template into text, variables and tags, with the line each starts on.
+fn tokenize(file: &str, src: &str) -> Result<Vec<(Token, usize)>, TemplateError> {
+    let mut acc = Vec::new();
+    let mut rest = src;
+    let mut line = 1;
+
+    loop {
+        let pos = match (rest.find("{{"), rest.find("{%")) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => {
+                if !rest.is_empty() { acc.push((Token::Text(rest.to_string()), line)) }
+                return Ok(acc)
+            },
+        };
+
+        let text = &rest[..pos];
+        if !text.is_empty() { acc.push((Token::Text(text.to_string()), line)) }
+        line += text.matches('\n').count();
+
+        let after = &rest[pos..];
+        let (open, close) = if after.starts_with("{{{") {
+            ("{{{", "}}}")
+        } else if after.starts_with("{{") {
+            ("{{", "}}")
+        } else {
+            ("{%", "%}")
+        };
+
+        let inner_start = &after[open.len()..];
+        let end = inner_start
+            .find(close)
+            .ok_or(TemplateError::new(file, line, format!("'{}' is not closed with '{}'", open, close)))?;
+        let inner = inner_start[..end].trim().to_string();
+
+        let token = match open {
+            "{{{" => Token::Var { path: inner, raw: true },
+            "{{" => Token::Var { path: inner, raw: false },
+            _ => Token::Tag(inner),
+        };
+        acc.push((token, line));
+
+        line += inner_start[..end].matches('\n').count();
+        rest = &inner_start[end + close.len()..];
+    }
+}
+
+// Parse tokens into nodes until one of `ends` is found, returning the nodes and the end tag.
+fn parse_block(
+    file: &str,
+    tokens: &[(Token, usize)],
+    pos: &mut usize,
+    ends: &[&str]) -> Result<(Vec<Node>, Option<String>), TemplateError>
+{
+    let mut acc = Vec::new();
+
+    while *pos < tokens.len() {
+        let (token, line) = &tokens[*pos];
+        let line = *line;
+        *pos += 1;
+
+        match token {
+            Token::Text(s) => acc.push(Node::Text(s.clone())),
+            Token::Var { path, raw } => {
+                if path.is_empty() {
+                    return Err(TemplateError::new(file, line, "Empty variable".to_string()))
+                }
+                acc.push(Node::Var { path: path.clone(), raw: *raw, line })
+            },
+            Token::Tag(tag) => {
+                let words: Vec<&str> = tag.split_whitespace().collect();
+                match words.as_slice() {
+                    [end] if ends.contains(end) => return Ok((acc, Some(end.to_string()))),
+                    ["for", name, "in", path] => {
+                        let (body, end) = parse_block(file, tokens, pos, &["endfor"])?;
+                        if end.is_none() {
+                            return Err(TemplateError::new(file, line, "'for' without 'endfor'".to_string()))
+                        }
+                        acc.push(Node::For { name: name.to_string(), path: path.to_string(), body, line })
+                    },
+                    ["if", path] => {
+                        let (then, end) = parse_block(file, tokens, pos, &["else", "endif"])?;
+                        let otherwise = match end.as_deref() {
+                            Some("else") => {
+                                let (otherwise, end) = parse_block(file, tokens, pos, &["endif"])?;
+                                if end.is_none() {
+                                    return Err(TemplateError::new(file, line, "'if' without 'endif'".to_string()))
+                                }
+                                otherwise
+                            },
+                            Some(_) => Vec::new(),
+                            None => {
+                                return Err(TemplateError::new(file, line, "'if' without 'endif'".to_string()))
+                            },
+                        };
+                        acc.push(Node::If { path: path.to_string(), then, otherwise, line })
+                    },
+                    ["include", name] => {
+                        let name = name.trim_matches('"');
+                        acc.push(Node::Include { name: name.to_string(), line })
+                    },
+                    _ => return Err(TemplateError::new(file, line, format!("Unexpected tag [{}]", tag))),
+                }
+            },
+        }
+    }
+    Ok((acc, None))
+}
+
+fn parse(file: &str, src: &str) -> Result<Vec<Node>, TemplateError> {
+    let tokens = tokenize(file, src)?;
+    let mut pos = 0;
+    let (nodes, _) = parse_block(file, &tokens, &mut pos, &[])?;
+    Ok(nodes)
+}
+
+// === Templates ==================================================================================
+
+/// Parsed templates, keyed by file name such as `page.html`.
+#[derive(Debug)]
+pub struct Templates(BTreeMap<String, Vec<Node>>);
+
+impl Templates {
+
+    /// Load and parse every template in `ts_graphics/templates`.
+    pub fn new<P: AsRef<Path>>(data_root: P) -> Result<Self> {
+        let mut map = BTreeMap::new();
+        for path in TSHtmlTemplate.into_resources(data_root)?.iter() {
+            let name = file_name(&path)?.to_string();
+            let src = fs::read_to_string(&path)?;
+            map.insert(name.clone(), parse(&name, &src)?);
+        }
+        Ok(Templates(map))
+    }
+
+    /// Parse templates from `(name, source)` pairs.
+    pub fn from_sources(sources: Vec<(&str, &str)>) -> Result<Self, TemplateError> {
+        let mut map = BTreeMap::new();
+        for (name, src) in sources {
+            map.insert(name.to_string(), parse(name, src)?);
+        }
+        Ok(Templates(map))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Render a template.
+    pub fn render(&self, name: &str, context: &Context) -> Result<String, TemplateError> {
+        let nodes = self.0
+            .get(name)
+            .ok_or(TemplateError::new(name, 0, format!("Template [{}] not found", name)))?;
+
+        let mut acc = String::new();
+        let mut scope = Scope { context, locals: Vec::new() };
+        self.render_nodes(name, nodes, &mut scope, 0, &mut acc)?;
+        Ok(acc)
+    }
+
+    fn render_nodes<'a>(
+        &'a self,
+        file: &str,
+        nodes: &'a [Node],
+        scope: &mut Scope<'a>,
+        depth: usize,
+        acc: &mut String) -> Result<(), TemplateError>
+    {
+        for node in nodes {
+            match node {
+                Node::Text(s) => acc.push_str(s),
+                Node::Var { path, raw, line } => {
+                    match scope.lookup(path) {
+                        Some(Value::Str(s)) => {
+                            match raw {
+                                true => acc.push_str(s),
+                                false => acc.push_str(&escape(s)),
+                            }
+                        },
+                        Some(_) => {
+                            return Err(TemplateError::new(file, *line, format!("Variable [{}] is not text", path)))
+                        },
+                        None => {
+                            return Err(TemplateError::new(file, *line, format!("Variable [{}] not found", path)))
+                        },
+                    }
+                },
+                Node::For { name, path, body, line } => {
+                    let items = match scope.lookup(path) {
+                        Some(Value::List(items)) => items,
+                        Some(_) => {
+                            return Err(TemplateError::new(file, *line, format!("Variable [{}] is not a list", path)))
+                        },
+                        None => {
+                            return Err(TemplateError::new(file, *line, format!("Variable [{}] not found", path)))
+                        },
+                    };
+                    for item in items.iter() {
+                        scope.locals.push((name.as_str(), item));
+                        let result = self.render_nodes(file, body, scope, depth, acc);
+                        scope.locals.pop();
+                        result?;
+                    }
+                },
+                Node::If { path, then, otherwise, .. } => {
+                    let branch = match scope.lookup(path) {
+                        Some(value) if value.is_truthy() => then,
+                        _ => otherwise,
+                    };
+                    self.render_nodes(file, branch, scope, depth, acc)?;
+                },
+                Node::Include { name, line } => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(TemplateError::new(file, *line, format!("Includes of [{}] nest too deeply", name)))
+                    }
+                    let nodes = self.0
+                        .get(name)
+                        .ok_or(TemplateError::new(file, *line, format!("Template [{}] not found", name)))?;
+                    self.render_nodes(name, nodes, scope, depth + 1, acc)?;
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+// The root context and the loop variables in scope.
+struct Scope<'a> {
+    context: &'a Context,
+    locals: Vec<(&'a str, &'a Value)>,
+}
+
+impl<'a> Scope<'a> {
+
+    // Find a dotted path like `graphic.note`, looking in loop variables before the context.
+    fn lookup(&self, path: &str) -> Option<&'a Value> {
+        let mut segments = path.split('.');
+        let first = segments.next()?;
+
+        let mut value: &'a Value = match self.locals.iter().rev().find(|(name, _)| *name == first) {
+            Some((_, value)) => value,
+            None => self.context.get(first)?,
+        };
+
+        for segment in segments {
+            value = match value {
+                Value::Map(map) => map.get(segment)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn context() -> Context {
+        let mut graphic = Context::new();
+        graphic.insert("note".into(), "a < b".into());
+
+        let mut context = Context::new();
+        context.insert("graphics".into(), Value::List(vec!(Value::Map(graphic), Value::Map(Context::new()))));
+        context
+    }
+
+    #[test]
+    fn loop_and_if_should_render() {
+        let templates = Templates::from_sources(vec!((
+            "page.html",
+            "{% for g in graphics %}[{% if g.note %}{{ g.note }}{% else %}none{% endif %}]{% endfor %}",
+        ))).unwrap();
+        assert_eq!(templates.render("page.html", &context()).unwrap(), "[a &lt; b][none]");
+    }
+
+    #[test]
+    fn missing_variable_should_report_file_and_line() {
+        let templates = Templates::from_sources(vec!(("page.html", "<html>\n\n{{ title }}"))).unwrap();
+        let e = templates.render("page.html", &context()).unwrap_err();
+        assert_eq!(e.to_string(), "page.html:3: Variable [title] not found");
+    }
+
+    #[test]
+    fn unclosed_for_should_fail_to_parse() {
+        let e = Templates::from_sources(vec!(("page.html", "\n{% for g in graphics %}"))).unwrap_err();
+        assert_eq!(e, TemplateError::new("page.html", 2, "'for' without 'endfor'".to_string()));
+    }
+
+    #[test]
+    fn template_error_should_keep_file_and_line_in_crate_error() {
+        let e = Templates::from_sources(vec!(("page.html", "\n{% for g in graphics %}"))).unwrap_err();
+        let e = crate::error::Error::from(e);
+        assert!(matches!(&e, crate::error::Error::Template(e) if e.line == 2));
+        assert_eq!(e.to_string(), "page.html:2: 'for' without 'endfor'");
+    }
+
+    #[test]
+    fn recursive_include_should_fail() {
+        let templates = Templates::from_sources(vec!(("a.html", "{% include \"a.html\" %}"))).unwrap();
+        assert!(templates.render("a.html", &context()).is_err());
+    }
+}
@@ -0,0 +1,388 @@
+//! Generate a [`TSSpec`](../struct.TSSpec.html) from a series specification, with one page per
+//! country and data type. Each page has a collation graphic of all its series followed by a
+//! source graphic per series, such as
+//! ```text
+//! page:
+//!     country:        Australia
+//!     data_type:      u
+//!     index:          0
+//!
+//!     series:
+//!         data_type:  u
+//!         series_id:  AUSURAMS
+//!     series:
+//!         data_type:  u
+//!         series_id:  AUSURANAA
+//!
+//!     graphic:
+//!         category:   collation
+//!         series_id:  AUSURAMS
+//!         series_id:  AUSURANAA
+//!     graphic:
+//!         category:   source
+//!         series_id:  AUSURAMS
+//!     graphic:
+//!         category:   source
+//!         series_id:  AUSURANAA
+//! ```
+//! When regenerating, the notes and ranges of graphics with the same category and series, and the
+//! index and height of pages, are kept from the previous spec. Graphics which are not generated,
+//! such as cleaned graphics, are kept after the generated ones with the series they show, and so
+//! are pages other than the first of each country and data type.
+
+use crate::{
+    countries::Country,
+    error::Result,
+    file_resources::IntoResources,
+    file_resources::impls::{MetaData, TSPageSpec},
+    meta_data,
+    primitives::{DataType, SeriesId},
+    series_spec::{series_spec_from_file, SeriessSpec},
+    spec_files::load_spec_files,
+    ts_graphics::TSGraphicCategory,
+    ts_graphics::ts_spec::{
+        ts_spec_from_file,
+        write_keeping_includes,
+        GraphicSpec,
+        PageSpec,
+        Series,
+        TSSpec,
+        TS_SPEC_SCHEMA,
+    },
+};
+use key_tree::KeyTree;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+// Identifies a graphic across regenerations.
+type GraphicKey = (DataType, Country, Option<String>, Vec<SeriesId>);
+
+/// Generate a `TSSpec` from a series specification. Source graphics without a note are captioned
+/// with the title in `titles`, if there is one.
+pub fn generate(
+    seriess: &SeriessSpec,
+    previous: Option<&TSSpec>,
+    titles: &BTreeMap<SeriesId, String>) -> TSSpec
+{
+    let mut buckets: BTreeMap<(DataType, Country), Vec<SeriesId>> = BTreeMap::new();
+    for series_spec in seriess.iter() {
+        let series_ids = buckets
+            .entry((series_spec.data_type(), series_spec.country()))
+            .or_default();
+        if !series_ids.contains(&series_spec.series_id()) {
+            series_ids.push(series_spec.series_id());
+        }
+    }
+
+    let mut previous_pages = HashMap::new();
+    let mut previous_graphics: HashMap<GraphicKey, &GraphicSpec> = HashMap::new();
+    for page in previous.iter().flat_map(|ts_spec| ts_spec.pages.iter()) {
+        previous_pages.entry((page.data_type, page.country)).or_insert(page);
+        for graphic in page.graphics.iter() {
+            previous_graphics.insert(graphic_key(page.data_type, page.country, graphic), graphic);
+        }
+    }
+
+    // Pages other than the one regenerated for each country and data type were added by hand.
+    let mut extra_pages = Vec::new();
+    for page in previous.iter().flat_map(|ts_spec| ts_spec.pages.iter()) {
+        let regenerated = buckets.contains_key(&(page.data_type, page.country)) &&
+            previous_pages[&(page.data_type, page.country)].index == page.index;
+        if !regenerated {
+            extra_pages.push(page.clone());
+        }
+    }
+
+    let mut pages = Vec::new();
+    for ((data_type, country), series_ids) in buckets {
+        let previous_page = previous_pages.get(&(data_type, country));
+
+        let mut graphics = vec!(GraphicSpec {
+            category_opt:   Some(TSGraphicCategory::Collation),
+            series_ids:     series_ids.clone(),
+            graphic_range:  None,
+            note:           None,
+        });
+        for series_id in series_ids.iter() {
+            graphics.push(GraphicSpec {
+                category_opt:   Some(TSGraphicCategory::Source),
+                series_ids:     vec!(series_id.clone()),
+                graphic_range:  None,
+                note:           titles.get(series_id).cloned(),
+            });
+        }
+
+        for graphic in graphics.iter_mut() {
+            if let Some(old) = previous_graphics.get(&graphic_key(data_type, country, graphic)) {
+                keep_hand_written(graphic, old);
+            }
+        }
+
+        let mut seriess: Vec<Series> = series_ids
+            .into_iter()
+            .map(|series_id| Series { data_type, series_id })
+            .collect();
+        if let Some(page) = previous_page {
+            keep_hand_written_graphics(page, &mut seriess, &mut graphics);
+        }
+
+        pages.push(PageSpec {
+            country,
+            data_type,
+            index:      previous_page.map_or(0, |page| page.index),
+            height_opt: previous_page.and_then(|page| page.height_opt),
+            seriess,
+            graphics,
+        });
+    }
+    pages.extend(extra_pages);
+    TSSpec { pages }
+}
+
+// Copy the graphics of a previous page which are not generated, such as cleaned graphics, and the
+// series of the previous page which they show.
+fn keep_hand_written_graphics(
+    previous: &PageSpec,
+    seriess: &mut Vec<Series>,
+    graphics: &mut Vec<GraphicSpec>)
+{
+    let generated = |graphic: &GraphicSpec| matches!(
+        graphic.category_opt,
+        Some(TSGraphicCategory::Collation) | Some(TSGraphicCategory::Source),
+    );
+    for graphic in previous.graphics.iter().filter(|graphic| !generated(graphic)) {
+        for series_id in graphic.series_ids.iter() {
+            let stem = series_id.stem();
+            if seriess.iter().any(|series| series.series_id == stem) { continue }
+            if let Some(series) = previous.series(&stem) {
+                seriess.push(series.clone());
+            }
+        }
+        graphics.push(graphic.clone());
+    }
+}
+
+// Copy the note and range of a graphic from a previous spec, if they were set.
+fn keep_hand_written(graphic: &mut GraphicSpec, old: &GraphicSpec) {
+    if old.note.is_some() {
+        graphic.note = old.note.clone();
+    }
+    if old.graphic_range.is_some() {
+        graphic.graphic_range = old.graphic_range;
+    }
+}
+
+fn graphic_key(data_type: DataType, country: Country, graphic: &GraphicSpec) -> GraphicKey {
+    (
+        data_type,
+        country,
+        graphic.category_opt.as_ref().map(|category| category.to_string()),
+        graphic.series_ids.clone(),
+    )
+}
+
+/// The titles of the series with a `.meta` file.
+pub fn titles_from_meta<P>(data_root: P, seriess: &SeriessSpec) -> Result<BTreeMap<SeriesId, String>>
+where
+    P: AsRef<Path>,
+{
+    let root = data_root.as_ref();
+    let mut acc = BTreeMap::new();
+    for series_spec in seriess.iter() {
+        let meta = MetaData { country: series_spec.country(), data_type: series_spec.data_type() };
+        let file = PathBuf::from(series_spec.series_id().stem().to_string()).with_extension("meta");
+        if !meta.has_file(root, &file).unwrap_or(false) { continue }
+
+        let series: meta_data::Series = KeyTree::parse(meta.full_path(root, &file)?)?.try_into()?;
+        acc.insert(series_spec.series_id(), series.title().to_string());
+    }
+    Ok(acc)
+}
+
+/// Generate a `TSSpec` from a file in the `spec` directory and write it to a file in the
+/// `ts_graphics/spec` directory, keeping what was written by hand in the file if it exists. Pages
+/// in files that the file includes are left to those files, and the include lines are kept. If
+/// `with_meta` is set, source graphics are captioned with the titles in the `.meta` files. Returns
+/// the spec of the file and its includes.
+pub fn generate_ts_spec_file<P, S, T>(
+    data_root: P,
+    series_spec_file: S,
+    ts_spec_file: T,
+    with_meta: bool) -> Result<TSSpec>
+where
+    P: AsRef<Path>,
+    S: AsRef<Path>,
+    T: AsRef<OsStr>,
+{
+    let root = data_root.as_ref();
+    let seriess = series_spec_from_file(root, series_spec_file.as_ref())?;
+
+    let path = TSPageSpec.dir(root)?.join(ts_spec_file.as_ref());
+
+    let mut included = HashSet::new();
+    let previous = match TSPageSpec.has_file(root, &ts_spec_file).unwrap_or(false) {
+        true => {
+            let main = path.canonicalize()?;
+            let fragments = load_spec_files::<TSSpec>(&[main.clone()], TS_SPEC_SCHEMA)?;
+            for (_, fragment) in fragments.iter().filter(|(file, _)| file != &main) {
+                for page in fragment.pages.iter() {
+                    included.insert((page.data_type, page.country, page.index));
+                }
+            }
+            Some(TSSpec::merge(fragments)?)
+        },
+        false => None,
+    };
+    let titles = match with_meta {
+        true => titles_from_meta(root, &seriess)?,
+        false => BTreeMap::new(),
+    };
+
+    let mut ts_spec = generate(&seriess, previous.as_ref(), &titles);
+    ts_spec.pages.retain(|page| !included.contains(&(page.data_type, page.country, page.index)));
+    write_keeping_includes(&path, &ts_spec)?;
+
+    Ok(ts_spec_from_file(root, &ts_spec_file)?)
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::ts_graphics::ts_spec::GraphicRange;
+    use std::fs;
+
+    fn seriess() -> SeriessSpec {
+        KeyTree::parse_str(r#"
+            seriess:
+                series:
+                    data_type:          u
+                    country:            Australia
+                    series_id:          AUSURAMS
+                series:
+                    data_type:          u
+                    country:            Australia
+                    series_id:          AUSURANAA
+                series:
+                    data_type:          u
+                    country:            New Zealand
+                    series_id:          LRHUTTTTNZQ156S
+        "#).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn generate_should_make_a_page_per_country_and_data_type() {
+        let ts_spec = generate(&seriess(), None, &BTreeMap::new());
+        assert_eq!(ts_spec.pages.len(), 2);
+
+        let page = &ts_spec.pages[0];
+        assert_eq!(page.graphics.len(), 3);
+        assert_eq!(page.graphics[0].category_opt, Some(TSGraphicCategory::Collation));
+        assert_eq!(page.graphics[0].series_ids.len(), 2);
+        assert!(ts_spec.validate().is_empty());
+    }
+
+    #[test]
+    fn generate_should_keep_notes_and_ranges() {
+        let mut previous = generate(&seriess(), None, &BTreeMap::new());
+        previous.pages[0].index = 3;
+        previous.pages[0].graphics[1].note = Some("Monthly.".to_string());
+        previous.pages[0].graphics[1].graphic_range = Some(GraphicRange::new(0.0, 12.0));
+
+        let mut titles = BTreeMap::new();
+        titles.insert(SeriesId::new("AUSURAMS"), "Unemployment Rate".to_string());
+        titles.insert(SeriesId::new("AUSURANAA"), "Unemployment Rate, Annual".to_string());
+
+        let ts_spec = generate(&seriess(), Some(&previous), &titles);
+        let page = &ts_spec.pages[0];
+        assert_eq!(page.index, 3);
+        assert_eq!(page.graphics[1].note.as_deref(), Some("Monthly."));
+        assert_eq!(page.graphics[1].graphic_range.unwrap().to_string(), "0 to 12");
+        assert_eq!(page.graphics[2].note.as_deref(), Some("Unemployment Rate, Annual"));
+    }
+
+    #[test]
+    fn regenerating_should_keep_includes_and_hand_written_pages_and_graphics() {
+        let root = std::env::temp_dir()
+            .join(format!("graphics_pipeline_generate_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("specs")).unwrap();
+        fs::create_dir_all(root.join("ts_graphics/spec/pages")).unwrap();
+
+        fs::write(root.join("specs/series_spec.keytree"), "seriess:
+    series:
+        data_type:  u
+        country:    Australia
+        series_id:  AUSURAMS
+    series:
+        data_type:  u
+        country:    New Zealand
+        series_id:  LRHUTTTTNZQ156S
+").unwrap();
+        let main = root.join("ts_graphics/spec/ts_page_spec.keytree");
+        fs::write(&main, "ts_spec:
+    include:    pages/new_zealand.keytree
+    page:
+        country:        Australia
+        data_type:      u
+        index:          0
+
+        series:
+            data_type:  u
+            series_id:  AUSURAMS
+
+        graphic:
+            category:   source
+            note:       Monthly.
+            series_id:  AUSURAMS
+        graphic:
+            category:   cleaned
+            series_id:  AUSURAMS_a
+    page:
+        country:        Australia
+        data_type:      u
+        index:          1
+
+        series:
+            data_type:  u
+            series_id:  AUSURAMS
+").unwrap();
+        let new_zealand = "ts_spec:
+    page:
+        country:        New Zealand
+        data_type:      u
+        index:          0
+
+        series:
+            data_type:  u
+            series_id:  LRHUTTTTNZQ156S
+";
+        fs::write(root.join("ts_graphics/spec/pages/new_zealand.keytree"), new_zealand).unwrap();
+
+        for _ in 0..2 {
+            let ts_spec = generate_ts_spec_file(
+                &root,
+                "series_spec.keytree",
+                "ts_page_spec.keytree",
+                false,
+            ).unwrap();
+            assert_eq!(ts_spec.pages.len(), 3);
+        }
+
+        let s = fs::read_to_string(&main).unwrap();
+        assert!(s.contains("include:    pages/new_zealand.keytree"));
+        assert!(!s.contains("New Zealand"));
+        assert!(s.contains("Monthly."));
+        assert!(s.contains("AUSURAMS_a"));
+        assert!(s.lines().any(|line| line.split_whitespace().eq(["index:", "1"])));
+        assert_eq!(
+            fs::read_to_string(root.join("ts_graphics/spec/pages/new_zealand.keytree")).unwrap(),
+            new_zealand,
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,16 +1,122 @@
 #![allow(dead_code)]
 
-use anyhow::{bail, Context, Error, Result};
+use anyhow::{bail, Context};
 use crate::{
     countries::Country,
+    error::{Error, Result},
+    file_resources::IntoResources,
+    file_resources::impls::TSPageSpec,
     ts_graphics::TSGraphicCategory,
+    ts_graphics::svg::nice_step,
     primitives::{DataType, SeriesId},
+    spec_files::{include_path, load_spec_files},
+    spec_files::diagnostics::{parses, KeyRule, SpecError, SpecErrors},
 };
+pub use crate::page_spec::{PageSpec, Series};
 use std::str::FromStr;
 use key_tree::{KeyTree, KeyTreeError};
 use key_tree::serialize::{KeyTreeString, IntoKeyTree};
 use serde::Serialize;
-use std::fmt;
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Generate a `TSSpec` from a series specification.
+pub mod generate;
+
+/// The padding added to each side of a computed range, as a fraction of the span of the data.
+pub const RANGE_PADDING: f32 = 0.05;
+
+/// The most ticks a computed range is snapped to.
+pub const RANGE_TICKS: usize = 5;
+
+/// The keys of a `TSSpec`.
+pub const TS_SPEC_SCHEMA: &[KeyRule] = &[
+    KeyRule::block("ts_spec", false),
+    KeyRule::block("ts_spec::page", false),
+    KeyRule::value("ts_spec::page::country", true, "country", parses::<Country>),
+    KeyRule::value("ts_spec::page::data_type", true, "data type", parses::<DataType>),
+    KeyRule::value("ts_spec::page::index", true, "page index", parses::<usize>),
+    KeyRule::value("ts_spec::page::height", false, "height", parses::<f32>),
+    KeyRule::block("ts_spec::page::series", false),
+    KeyRule::value("ts_spec::page::series::data_type", true, "data type", parses::<DataType>),
+    KeyRule::value("ts_spec::page::series::series_id", true, "series id", parses::<SeriesId>),
+    KeyRule::block("ts_spec::page::graphic", false),
+    KeyRule::value(
+        "ts_spec::page::graphic::category",
+        false,
+        "graphic category",
+        parses::<TSGraphicCategory>,
+    ),
+    KeyRule::value(
+        "ts_spec::page::graphic::range",
+        false,
+        "range like '0 to 12'",
+        parses::<GraphicRange>,
+    ),
+    KeyRule::value("ts_spec::page::graphic::note", false, "note", parses::<String>),
+    KeyRule::value("ts_spec::page::graphic::series_id", true, "series id", parses::<SeriesId>),
+];
+
+/// Return the `TSSpec` in a file in the `ts_graphics/spec` directory.
+/// ```
+/// # use graphics_pipeline::ts_graphics::ts_spec::ts_spec_from_file;
+/// let _ = ts_spec_from_file("../../shared_data", "ts_page_spec.keytree").unwrap();
+/// ```
+pub fn ts_spec_from_file<P, S>(data_root: P, file: S) -> Result<TSSpec>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let path = TSPageSpec.full_path(data_root, file)?;
+    TSSpec::merge(load_spec_files(&[path], TS_SPEC_SCHEMA)?)
+}
+
+/// Return the pages of every file in the `ts_graphics/spec` directory, and the files they
+/// include, as one `TSSpec`.
+pub fn ts_spec_from_resources<P: AsRef<Path>>(data_root: P) -> Result<TSSpec> {
+    let paths: Vec<PathBuf> = TSPageSpec.into_resources(data_root)?.iter().collect();
+    TSSpec::merge(load_spec_files(&paths, TS_SPEC_SCHEMA)?)
+}
+
+/// Compute the range of every graphic in a file in the `ts_graphics/spec` directory which has
+/// none, and write them back, returning the number of ranges written. Each included file is
+/// rewritten from its parsed spec, so comments are not kept, but includes are.
+pub fn write_ranges<P, S>(data_root: P, file: S) -> Result<usize>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let root = data_root.as_ref();
+    let path = TSPageSpec.full_path(root, &file)?;
+
+    let mut count = 0;
+    for (path, mut ts_spec) in load_spec_files::<TSSpec>(&[path], TS_SPEC_SCHEMA)? {
+        let n = ts_spec.fill_ranges(root)?;
+        if n == 0 { continue }
+        write_keeping_includes(&path, &ts_spec)?;
+        count += n;
+    }
+    Ok(count)
+}
+
+/// Write the pages of `ts_spec` to a file, keeping the include lines of the file if it exists.
+/// Comments are not kept.
+pub fn write_keeping_includes(path: &Path, ts_spec: &TSSpec) -> Result<()> {
+    let original = fs::read_to_string(path).unwrap_or_default();
+    let includes = original.lines().filter(|line| include_path(line).is_some());
+
+    // Put the include lines of the file back under the root key.
+    let mut lines: Vec<String> = ts_spec.keytree().to_string().lines().map(String::from).collect();
+    lines.splice(1..1, includes.map(String::from));
+    fs::write(path, lines.join("\n") + "\n")
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    Ok(())
+}
 
 // impl SpecFromFile for TSSpec {}
 
@@ -25,100 +131,98 @@ impl TryInto<TSSpec> for KeyTree {
     type Error = KeyTreeError;
 
     fn try_into(self) -> std::result::Result<TSSpec, Self::Error> {
-        Ok(TSSpec{ pages: self.vec_at("ts_spec::graphic")? })
+        Ok(TSSpec{ pages: self.opt_vec_at("ts_spec::page")? })
     }
 }
 
-// === PageSpec ===================================================================================
-
-/// Component of [`TSSpec`](struct.TSSpec.html).
-/// ```
-/// # use key_tree::KeyTree;
-/// # use graphics_pipeline::ts_graphics::ts_spec::PageSpec;
-/// # let s = "
-///     page:
-///         country:        Australia
-///         data_type:      u
-///         index:          0
-///
-///         series:
-///             data_type:  u
-///             series_id:  AUSURAMS
-///         series:
-///             data_type:  u
-///             series_id:  AUSURANAA
-///
-///         graphic:
-///             category:   collation
-///             series_id:  AUSURAMS
-///             series_id:  AUSURANAA
-/// # ";
-/// # let _ps: PageSpec = KeyTree::parse_str(s)
-///     .unwrap()
-///     .try_into()
-///     .unwrap();
-/// ```
-#[derive(Debug)]
-pub struct PageSpec {
-    pub(crate) country: Country,
-    pub(crate) data_type: DataType,
-    pub(crate) index: usize,
-    pub(crate) height_opt: Option<f32>,
-    pub(crate) seriess: Vec<Series>,
-    pub(crate) graphics: Vec<GraphicSpec>,
-}
+impl TSSpec {
+
+    /// Merge the fragments of a spec, each with the file it came from. Each data type, country
+    /// and index may only have one page.
+    pub fn merge(fragments: Vec<(PathBuf, TSSpec)>) -> Result<TSSpec> {
+        let mut seen: HashMap<(DataType, Country, usize), PathBuf> = HashMap::new();
+        let mut duplicates = Vec::new();
+        let mut pages = Vec::new();
+
+        for (path, fragment) in fragments {
+            for page in fragment.pages {
+                let key = (page.data_type, page.country, page.index);
+                match seen.get(&key) {
+                    Some(first) => duplicates.push(SpecError {
+                        file:       path.clone(),
+                        line:       None,
+                        key_path:   String::new(),
+                        value:      None,
+                        message:    format!(
+                            "Page [{} {} {}] is already in '{}'",
+                            page.data_type,
+                            page.country,
+                            page.index,
+                            first.display(),
+                        ),
+                    }),
+                    None => {
+                        seen.insert(key, path.clone());
+                        pages.push(page);
+                    },
+                }
+            }
+        }
+        if !duplicates.is_empty() {
+            return Err(Error::Spec(SpecErrors(duplicates)))
+        }
+        Ok(TSSpec { pages })
+    }
 
-impl TryInto<PageSpec> for KeyTree {
-    type Error = KeyTreeError;
+    /// Check the graphics of every page, returning every violation found:
+    /// - every series of a graphic, or the series it is transformed from, is in the page's
+    ///   `series`, which may have other data types than the page,
+    /// - a `source` graphic has exactly one series, which is not transformed,
+    /// - a `collation` graphic has every series of the page,
+    /// - every series of a `cleaned` graphic is transformed, like `AUSURAMS_a`.
+    pub fn validate(&self) -> Vec<SpecViolation> {
+        self.pages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| validate_page(page, &format!("ts_spec::page[{}]", i)))
+            .collect()
+    }
 
-    fn try_into(self) -> std::result::Result<PageSpec, Self::Error> {
-        Ok(
-            PageSpec {
-                country:    self.from_str("page::country")?, 
-                data_type:  self.from_str("page::data_type")?,
-                index:      self.from_str("page::index")?,
-                height_opt: self.opt_from_str("page::height")?,
-                seriess:    self.vec_at("page::series")?,
-                graphics:   self.vec_at("page::graphic")?,
+    /// Compute the range of every graphic which has none from the data under `data_root`,
+    /// returning the number of ranges set.
+    pub fn fill_ranges(&mut self, data_root: &Path) -> Result<usize> {
+        let mut count = 0;
+        for page in self.pages.iter_mut() {
+            for i in 0..page.graphics.len() {
+                if page.graphics[i].graphic_range.is_some() { continue }
+                let range = page.auto_range(data_root, &page.graphics[i])?;
+                if range.is_some() { count += 1 }
+                page.graphics[i].graphic_range = range;
             }
-        )
+        }
+        Ok(count)
     }
 }
 
-// === Series =====================================================================================
-
-/// The specification for a series, that is used across the build pipeline. The keytree representation
-/// looks like
-/// ```
-/// # use key_tree::KeyTree;
-/// # use graphics_pipeline::ts_graphics::ts_spec::Series;
-/// # let spec = r#"
-///   series:
-///       data_type:          u
-///       series_id:          LRHUTTTTAUA156S
-/// # "#;
-/// let _: Series = KeyTree::parse_str(spec)
-///     .unwrap()
-///     .try_into()
-///     .unwrap();
-/// ```
-#[derive(Clone, Debug)]
-pub struct Series {
-    pub(crate) data_type:      DataType,
-    pub(crate) series_id:      SeriesId,
+impl IntoKeyTree for TSSpec {
+    fn keytree(&self) -> KeyTreeString {
+        let mut kt = KeyTreeString::new();
+        kt.push_key(0, "ts_spec");
+        for page in &self.pages {
+            kt.push_keytree(1, page.keytree());
+        }
+        kt
+    }
 }
 
-impl<'a> TryInto<Series> for KeyTree {
-    type Error = KeyTreeError;
-
-    fn try_into(self) -> std::result::Result<Series, Self::Error> {
-        Ok(
-            Series {
-                data_type:  self.from_str("series::data_type")?, 
-                series_id:  self.from_str("series::series_id")?,
-            }
-        )
-    }
+/// Check the graphics of one page against the rules of
+/// [`TSSpec::validate`](struct.TSSpec.html#method.validate), reporting violations below `path`.
+pub fn validate_page(page: &PageSpec, path: &str) -> Vec<SpecViolation> {
+    page.graphics
+        .iter()
+        .enumerate()
+        .flat_map(|(j, graphic)| graphic.validate(page, &format!("{}::graphic[{}]", path, j)))
+        .collect()
 }
 
 // === GraphicSpec ================================================================================
@@ -140,7 +244,7 @@ impl<'a> TryInto<Series> for KeyTree {
 /// # assert_eq!(gs.category_opt, Some(TSGraphicCategory::Collation));
 /// # assert_eq!(gs.series_ids[0].to_string(), "AUSURAMS");
 /// ```
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct GraphicSpec {
     pub category_opt:   Option<TSGraphicCategory>,
     pub series_ids:     Vec<SeriesId>,
@@ -152,6 +256,57 @@ impl GraphicSpec {
     pub (crate) fn assert_has_one_series(&self) -> bool {
         self.series_ids.len() == 1
     }
+
+    // Check the graphic against the rules of its category, reporting violations at `path`.
+    fn validate(&self, page: &PageSpec, path: &str) -> Vec<SpecViolation> {
+        let mut acc = Vec::new();
+
+        for series_id in self.series_ids.iter() {
+            if page.series(&series_id.stem()).is_none() {
+                acc.push(SpecViolation::new(
+                    path,
+                    format!("Series [{}] is not a series of the page", series_id),
+                ));
+            }
+        }
+
+        match self.category_opt {
+            Some(TSGraphicCategory::Source) => {
+                if !self.assert_has_one_series() {
+                    acc.push(SpecViolation::new(
+                        path,
+                        format!("Source graphic has {} series, not one", self.series_ids.len()),
+                    ));
+                }
+                for series_id in self.series_ids.iter().filter(|series_id| series_id.is_transformed()) {
+                    acc.push(SpecViolation::new(
+                        path,
+                        format!("Source graphic has transformed series [{}]", series_id),
+                    ));
+                }
+            },
+            Some(TSGraphicCategory::Collation) => {
+                for series in page.seriess.iter() {
+                    if !self.series_ids.contains(&series.series_id) {
+                        acc.push(SpecViolation::new(
+                            path,
+                            format!("Collation graphic is missing series [{}]", series.series_id),
+                        ));
+                    }
+                }
+            },
+            Some(TSGraphicCategory::Cleaned) => {
+                for series_id in self.series_ids.iter().filter(|series_id| !series_id.is_transformed()) {
+                    acc.push(SpecViolation::new(
+                        path,
+                        format!("Cleaned graphic has series [{}] without a transform", series_id),
+                    ));
+                }
+            },
+            None => {},
+        }
+        acc
+    }
 }
 
 impl TryInto<GraphicSpec> for KeyTree {
@@ -174,12 +329,12 @@ impl IntoKeyTree for GraphicSpec {
         let mut kt = KeyTreeString::new();
         kt.push_key(0, "graphic" );
 
-        if let Some(class) = &self.category_opt {
-            kt.push_keyvalue(1, "class", class);
+        if let Some(category) = &self.category_opt {
+            kt.push_keyvalue(1, "category", category);
         }
 
         if let Some(range) = &self.graphic_range {
-            kt.push_keyvalue(1, "graphic", range);
+            kt.push_keyvalue(1, "range", range);
         }
 
         if let Some(note) = &self.note {
@@ -193,6 +348,28 @@ impl IntoKeyTree for GraphicSpec {
     }
 }
 
+// === SpecViolation ==============================================================================
+
+/// A rule broken by a `TSSpec`, at a key path like `ts_spec::page[0]::graphic[1]` where the
+/// indices count the keys of that name from zero.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecViolation {
+    pub path:       String,
+    pub message:    String,
+}
+
+impl SpecViolation {
+    fn new(path: &str, message: String) -> Self {
+        SpecViolation { path: path.to_string(), message }
+    }
+}
+
+impl fmt::Display for SpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
 // === GraphicRange ===============================================================================
 
 #[derive(Clone, Copy, Debug, Serialize)]
@@ -202,20 +379,69 @@ pub struct GraphicRange {
     max:    f32,
 }
 
-impl FromStr for GraphicRange {
-    type Err = Error;
+impl GraphicRange {
+    pub fn new(min: f32, max: f32) -> Self {
+        GraphicRange { min, max }
+    }
+
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+
+    /// Compute a range over `values`, padded by `RANGE_PADDING` and snapped to round ticks.
+    /// Unemployment ranges start at zero and inflation ranges are symmetric around zero. Returns
+    /// `None` if there are no values.
+    /// ```
+    /// # use graphics_pipeline::primitives::DataType;
+    /// # use graphics_pipeline::ts_graphics::ts_spec::GraphicRange;
+    /// let range = GraphicRange::auto(DataType::U, vec!(4.2, 9.3)).unwrap();
+    /// assert_eq!(range.to_string(), "0 to 10");
+    /// ```
+    pub fn auto<I: IntoIterator<Item = f32>>(data_type: DataType, values: I) -> Option<GraphicRange> {
+        let (mut min, mut max) = (f32::MAX, f32::MIN);
+        for value in values.into_iter().filter(|value| value.is_finite()) {
+            min = min.min(value);
+            max = max.max(value);
+        }
+        if min > max {
+            return None
+        }
+
+        let pad = match max - min {
+            span if span > 0.0 => span * RANGE_PADDING,
+            _ => max.abs().max(1.0) * RANGE_PADDING,
+        };
+        let (low, high) = match data_type {
+            DataType::U if min >= 0.0 => (0.0, max + pad),
+            DataType::Inf => {
+                let bound = min.abs().max(max.abs()) + pad;
+                (-bound, bound)
+            },
+            _ => (min - pad, max + pad),
+        };
+
+        let step = nice_step(high - low, RANGE_TICKS);
+        Some(GraphicRange::new((low / step).floor() * step, (high / step).ceil() * step))
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self> {
+impl FromStr for GraphicRange {
+    type Err = anyhow::Error;
 
-        let segment: Vec<&str> = s.split(" to ").collect();
+    fn from_str(s: &str) -> anyhow::Result<Self> {
 
-        if segment[0].is_empty() || segment[1].is_empty() {
-            bail!("Parse into GraphicRange failed.")
+        let (min, max) = match s.split_once(" to ") {
+            Some((min, max)) if !min.is_empty() && !max.is_empty() => (min, max),
+            _ => bail!("Parse into GraphicRange failed."),
         };
 
-        let min = segment[0].parse().context("Parse into GraphicRange failed.")?;
+        let min = min.parse().context("Parse into GraphicRange failed.")?;
 
-        let max = segment[1].parse().context("Parse into GraphicRange failed.")?;
+        let max = max.parse().context("Parse into GraphicRange failed.")?;
         
         Ok(GraphicRange { min, max })
     }
@@ -244,7 +470,141 @@ impl fmt::Display for GraphicRange {
 pub mod test {
 
     use key_tree::KeyTree;
-    use crate::ts_graphics::ts_spec::PageSpec;
+    use key_tree::serialize::IntoKeyTree;
+    use crate::primitives::{DataType, SeriesId};
+    use crate::spec_files::diagnostics::check_source;
+    use crate::ts_graphics::ts_spec::{GraphicRange, GraphicSpec, PageSpec, TSSpec, TS_SPEC_SCHEMA};
+    use std::path::Path;
+
+    #[test]
+    fn validate_should_report_every_violation() {
+        let s = r#"
+          ts_spec:
+              page:
+                  country:        Australia
+                  data_type:      u
+                  index:          0
+
+                  series:
+                      data_type:  u
+                      series_id:  AUSURAMS
+                  series:
+                      data_type:  u
+                      series_id:  AUSURANAA
+
+                  graphic:
+                      category:   collation
+                      series_id:  AUSURAMS
+                  graphic:
+                      category:   source
+                      series_id:  AUSURAMS
+                      series_id:  AUSURANAA
+                  graphic:
+                      category:   cleaned
+                      series_id:  AUSURAMS_a
+        "#;
+        let ts_spec: TSSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let violations: Vec<String> = ts_spec.validate().iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            violations,
+            vec!(
+                "ts_spec::page[0]::graphic[0]: Collation graphic is missing series [AUSURANAA]",
+                "ts_spec::page[0]::graphic[1]: Source graphic has 2 series, not one",
+            ),
+        );
+    }
+
+    #[test]
+    fn series_of_other_data_types_should_be_valid() {
+        let s = r#"
+          ts_spec:
+              page:
+                  country:        Australia
+                  data_type:      u
+                  index:          0
+
+                  series:
+                      data_type:  inf
+                      series_id:  AUSCPIALLQINMEI
+
+                  graphic:
+                      category:   cleaned
+                      series_id:  AUSCPIALLQINMEI_a
+                  graphic:
+                      category:   source
+                      series_id:  AUSURAMS
+        "#;
+        let ts_spec: TSSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let violations: Vec<String> = ts_spec.validate().iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            violations,
+            vec!("ts_spec::page[0]::graphic[1]: Series [AUSURAMS] is not a series of the page"),
+        );
+        assert_eq!(
+            ts_spec.pages[0].series_data_type(&SeriesId::new("AUSCPIALLQINMEI_a")),
+            DataType::Inf,
+        );
+    }
+
+    #[test]
+    fn malformed_range_should_be_reported() {
+        assert!("0-12".parse::<GraphicRange>().is_err());
+        assert!("0 to ".parse::<GraphicRange>().is_err());
+
+        let s = "ts_spec:
+    page:
+        country:        Australia
+        data_type:      u
+        index:          0
+
+        graphic:
+            range:      0-12
+            series_id:  AUSURAMS
+";
+        let errors: Vec<String> = check_source(Path::new("spec.keytree"), s, TS_SPEC_SCHEMA)
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec!(
+                "spec.keytree:8: ts_spec::page::graphic::range: \
+                    Failed to parse a range like '0 to 12' [0-12]",
+            ),
+        );
+    }
+
+    #[test]
+    fn auto_range_should_be_symmetric_for_inflation() {
+        let range = GraphicRange::auto(DataType::Inf, vec!(-1.5, 7.8)).unwrap();
+        assert_eq!(range.min(), -range.max());
+        assert!(range.max() >= 7.8);
+    }
+
+    #[test]
+    fn auto_range_should_pad_and_snap() {
+        let range = GraphicRange::auto(DataType::Cpi, vec!(101.3, 118.9)).unwrap();
+        assert_eq!(range.to_string(), "100 to 120");
+        assert!(GraphicRange::auto(DataType::Cpi, Vec::new()).is_none());
+    }
+
+    #[test]
+    fn graphic_keytree_should_round_trip() {
+        let s = r#"
+            graphic:
+                category:   source
+                range:      0 to 12
+                note:       Annual before 1986.
+                series_id:  AUSURAMS
+        "#;
+        let gs: GraphicSpec = KeyTree::parse_str(s).unwrap().try_into().unwrap();
+        let output: GraphicSpec = KeyTree::parse_str(&gs.keytree().to_string())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(output.graphic_range.unwrap().to_string(), "0 to 12");
+        assert_eq!(output.category_opt, gs.category_opt);
+    }
 
     #[test]
     fn pagespec_from_keytree_should_work() {
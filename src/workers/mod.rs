@@ -0,0 +1,165 @@
+//! Run batches of work on a bounded pool of threads.
+//!
+//! Work on series is grouped into buckets by `(DataType, Country)`, as in
+//! [`SeriesSpecMap`](../series_to_disk/struct.SeriesSpecMap.html). Up to `workers` buckets run at
+//! once and the series of a bucket run in order. Results come back in the order of the batch
+//! whatever order they finish in, and an item which fails is collected with its key rather than
+//! aborting the batch.
+//! ```
+//! # use graphics_pipeline::workers::map_bounded;
+//! let squares = map_bounded(vec!(1, 2, 3, 4, 5), 2, |n| n * n);
+//! assert_eq!(squares, vec!(1, 4, 9, 16, 25));
+//! ```
+
+use crate::{
+    error::{Error, Result},
+    primitives::SeriesId,
+    series_spec::SeriesSpec,
+    series_to_disk::SeriesSpecMap,
+};
+use std::{sync::Mutex, thread};
+
+/// The number of buckets run at once unless configured otherwise. This is kept small because data
+/// sources such as FRED limit the rate of requests.
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// Apply `f` to every item with at most `workers` threads, returning the results in the order of
+/// `items`. A panic in `f` is resumed on the calling thread.
+pub fn map_bounded<T, R, F>(items: Vec<T>, workers: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let len = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..len).map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, len.max(1)) {
+            scope.spawn(|| loop {
+                let next = queue.lock().unwrap().next();
+                let (i, item) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("Every item has a result"))
+        .collect()
+}
+
+/// Apply `f` to every series of a spec, running the buckets of each `(DataType, Country)` at once
+/// and the series of a bucket in order.
+pub fn map_series<T, F>(spec_map: &SeriesSpecMap, workers: usize, f: F) -> Batch<SeriesId, T>
+where
+    T: Send,
+    F: Fn(&SeriesSpec) -> Result<T> + Sync,
+{
+    let buckets: Vec<Vec<&SeriesSpec>> = spec_map
+        .buckets()
+        .map(|(_, inner)| inner.values().collect())
+        .collect();
+
+    map_bounded(buckets, workers, |bucket| {
+        bucket
+            .into_iter()
+            .map(|series_spec| (series_spec.series_id(), f(series_spec)))
+            .collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+// === Batch ======================================================================================
+
+/// The items of a batch which succeeded and those which failed, each in the order of the batch.
+#[derive(Debug)]
+pub struct Batch<K, T> {
+    pub done:   Vec<(K, T)>,
+    pub failed: Vec<(K, Error)>,
+}
+
+impl<K, T> Batch<K, T> {
+
+    /// Whether every item succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// The values of the items which succeeded.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.done.iter().map(|(_, value)| value)
+    }
+
+    /// Each failure as a line like `AUSURAMS: Data source failed: ..`.
+    pub fn failures(&self) -> Vec<String>
+    where
+        K: std::fmt::Display,
+    {
+        self.failed.iter().map(|(key, e)| format!("{}: {}", key, e)).collect()
+    }
+}
+
+impl<K, T> Default for Batch<K, T> {
+    fn default() -> Self {
+        Batch { done: Vec::new(), failed: Vec::new() }
+    }
+}
+
+impl<K, T> FromIterator<(K, Result<T>)> for Batch<K, T> {
+    fn from_iter<I: IntoIterator<Item = (K, Result<T>)>>(iter: I) -> Self {
+        let mut batch = Batch::default();
+        for (key, result) in iter {
+            match result {
+                Ok(value) => batch.done.push((key, value)),
+                Err(e) => batch.failed.push((key, e)),
+            }
+        }
+        batch
+    }
+}
+
+// === Tests ======================================================================================
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_should_keep_the_order_of_the_batch() {
+        // Earlier items take longer, so they finish last.
+        let items: Vec<u64> = (0..8).collect();
+        let results = map_bounded(items, 3, |n| {
+            thread::sleep(Duration::from_millis(40 - 5 * n));
+            n
+        });
+        assert_eq!(results, (0..8).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn failures_should_not_abort_the_batch() {
+        let batch: Batch<u32, u32> = map_bounded(vec!(1, 2, 3), 2, |n| {
+            let result = match n {
+                2 => Err(Error::Transform("bad series".to_string())),
+                n => Ok(n * 10),
+            };
+            (n, result)
+        })
+        .into_iter()
+        .collect();
+
+        assert_eq!(batch.done, vec!((1, 10), (3, 30)));
+        assert_eq!(batch.failures(), vec!("2: Transform failed: bad series"));
+    }
+}